@@ -0,0 +1,236 @@
+// Single premium immediate annuities: hand the insurer a lump sum, get an
+// income for life.  They're priced from a mortality table and the bond yield
+// in the year of purchase, so the same annuity is cheap in 1981 and dear in
+// 2020.
+
+// Probability of dying within the year, by age.
+#[derive(Clone)]
+pub struct Mortality {
+    start_age: usize,
+    qx: Vec<f64>,
+}
+
+impl Mortality {
+    // qx[i] is the probability that someone aged start_age + i dies before
+    // their next birthday, e.g. from the SSA period life table.  Everyone is
+    // assumed dead by the end of the table.
+    pub fn from_qx(start_age: usize, qx: Vec<f64>) -> Self {
+        assert!(qx.iter().all(|q| (0. ..=1.).contains(q)));
+        Mortality { start_age, qx }
+    }
+
+    // The Gompertz law of mortality, the usual two parameter approximation
+    // to a life table.  A modal age of 88 and dispersion of 10 is close to a
+    // 65 year old US couple's first death.
+    pub fn gompertz(modal_age: f64, dispersion: f64) -> Self {
+        let survival = |age: f64, years: f64| {
+            ((age - modal_age) / dispersion).exp() * (1. - (years / dispersion).exp())
+        };
+        let qx = (0..=120)
+            .map(|age| 1. - survival(age as f64, 1.).exp())
+            .collect();
+        Mortality { start_age: 0, qx }
+    }
+
+    fn qx(&self, age: usize) -> f64 {
+        assert!(age >= self.start_age);
+        *self.qx.get(age - self.start_age).unwrap_or(&1.)
+    }
+
+    // Probability that someone alive at `age` is still alive `years` later.
+    pub fn survival(&self, age: usize, years: usize) -> f64 {
+        (age..age + years).map(|a| 1. - self.qx(a)).product()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Payout {
+    Real,    // Inflation adjusted, like a TIPS ladder.
+    Nominal, // Fixed in dollars of the purchase year.
+}
+
+#[derive(Clone)]
+pub struct Annuity {
+    pub fraction: f64,        // Fraction of the portfolio spent on the premium.
+    pub purchase_year: usize, // Years after the start of retirement.
+    pub age: usize,           // Age at the start of retirement.
+    pub payout: Payout,
+    pub load: f64, // Insurer's costs and profit, as a fraction of the fair price.
+    mortality: Mortality,
+    yields: Vec<f64>,
+}
+
+impl Annuity {
+    // `yields` is the nominal bond yield in percent for each year of the
+    // backtest's dataset.
+    pub fn new(
+        fraction: f64,
+        purchase_year: usize,
+        age: usize,
+        payout: Payout,
+        mortality: Mortality,
+        yields: Vec<f64>,
+    ) -> Self {
+        assert!((0. ..=1.).contains(&fraction));
+        Annuity {
+            fraction,
+            purchase_year,
+            age,
+            payout,
+            load: 0.,
+            mortality,
+            yields,
+        }
+    }
+
+    pub(crate) fn years(&self) -> usize {
+        self.yields.len()
+    }
+
+    // Fair price of $1 a year, paid at the start of each year while alive
+    // starting at purchase, bought in the given dataset year.  `past` is
+    // 1 + inflation for up to ten years before the purchase, in the order
+    // the run lived through them.
+    //
+    // There's no historical real yield before TIPS, so for real payouts the
+    // nominal yield is deflated by the average inflation of those years, a
+    // rough stand in for expected inflation.
+    pub fn price(&self, index: usize, past: &[f64]) -> f64 {
+        let mut rate = 1. + self.yields[index] / 100.;
+        if self.payout == Payout::Real {
+            let expected = if past.is_empty() {
+                1.
+            } else {
+                past.iter().product::<f64>().powf(1. / past.len() as f64)
+            };
+            rate /= expected;
+        }
+
+        let mut age = self.age + self.purchase_year;
+        let mut price = 0.;
+        let mut alive = 1.;
+        let mut discount = 1.;
+        while alive > 1e-9 {
+            price += alive * discount;
+            alive *= 1. - self.mortality.qx(age);
+            discount /= rate;
+            age += 1;
+        }
+        price * (1. + self.load)
+    }
+}
+
+// An annuity once bought, for the rest of a single run.
+pub(crate) struct Purchased {
+    income: f64,           // Real, or nominal in purchase year dollars.
+    inflation_factor: f64, // Cumulative inflation from retirement to purchase.
+}
+
+impl Purchased {
+    // Buy the annuity from the portfolio at the given dataset year, after
+    // the `past` inflation, as for Annuity::price.
+    pub(crate) fn buy(
+        annuity: &Annuity,
+        portfolio: &mut f64,
+        index: usize,
+        past: &[f64],
+        inflation_factor: f64,
+    ) -> Self {
+        let premium = annuity.fraction * portfolio.max(0.);
+        *portfolio -= premium;
+        Purchased {
+            income: premium / annuity.price(index, past),
+            inflation_factor,
+        }
+    }
+
+    // This year's income in real dollars.
+    pub(crate) fn income(&self, payout: Payout, inflation_factor: f64) -> f64 {
+        match payout {
+            Payout::Real => self.income,
+            Payout::Nominal => self.income * self.inflation_factor / inflation_factor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Annuity, Mortality, Payout};
+    use crate::time_series;
+    use crate::windows::Sequence;
+    use crate::Backtest;
+
+    #[test]
+    fn price_is_expected_payments() {
+        // Certain to die at 69, so four payments at 65, 66, 67 and 68.
+        let mortality = Mortality::from_qx(65, vec![0., 0., 0., 1.]);
+        let yields = vec![0.; time_series::YEARS];
+        let inflation = [1.; 10];
+        let annuity = Annuity::new(1., 0, 65, Payout::Nominal, mortality.clone(), yields);
+        assert!((annuity.price(50, &inflation) - 4.).abs() < 1e-12);
+
+        let annuity = Annuity::new(1., 1, 65, Payout::Nominal, mortality, vec![10.; 150]);
+        assert!((annuity.price(50, &inflation) - (1. + 1. / 1.1 + 1. / 1.21)).abs() < 1e-12);
+
+        let gompertz = Mortality::gompertz(88., 10.);
+        assert!(gompertz.survival(65, 23) > 0.35 && gompertz.survival(65, 23) < 0.55);
+    }
+
+    #[test]
+    fn full_annuitization_pays_expenses() {
+        // A nominal annuity priced at a 0% yield over a fixed 4 year life pays
+        // a quarter of the premium every year, exactly covering a nominal
+        // expense of that amount.
+        let mortality = Mortality::from_qx(65, vec![0., 0., 0., 1.]);
+        let yields = vec![0.; time_series::YEARS];
+        let annuity = Annuity::new(1., 0, 65, Payout::Nominal, mortality, yields);
+        let backtest = Backtest::new(
+            1000.,
+            vec![0.; 4],
+            vec![250.; 4],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+        .with_annuity(annuity);
+
+        assert!(
+            backtest
+                .single_run(&[0.6; 4], 1973 - time_series::FIRST_YEAR, false)
+                .abs()
+                < 1e-9
+        );
+    }
+
+    #[test]
+    fn real_payout_looks_back_along_the_sequence() {
+        let annuity = Annuity::new(
+            1.,
+            3,
+            65,
+            Payout::Real,
+            Mortality::gompertz(88., 10.),
+            vec![5.; time_series::YEARS],
+        );
+        let backtest = Backtest::new(
+            1000.,
+            vec![0.; 5],
+            vec![0.; 5],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+        .with_sequence(Sequence::Reversed)
+        .with_annuity(annuity.clone());
+
+        // Reversed from 100, the purchase in year 3 is at 97, and the ten
+        // years lived through before it are 107 down to 98.
+        let mut purchased = vec![None];
+        let mut portfolio = 1000.;
+        let income = backtest.annuity_income(&mut purchased, 3, 100, &mut portfolio, 1.);
+        let past: Vec<f64> = (98..=107).rev().map(|i| backtest.inflation[i]).collect();
+        assert!((income - 1000. / annuity.price(97, &past)).abs() < 1e-9);
+        assert!(
+            (annuity.price(97, &past) - annuity.price(97, &backtest.inflation[87..97])).abs()
+                > 1e-3
+        );
+    }
+}
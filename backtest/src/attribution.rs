@@ -0,0 +1,196 @@
+use crate::stress::{Shock, ShockYear};
+use crate::Backtest;
+use std::fmt;
+
+// Why a window went badly.  Each year of the window in turn gets the window's
+// average returns and inflation instead of its own, and the change in the
+// ending value is that year's contribution.  A year with a large negative
+// contribution did the damage: had it been average, the plan would have
+// ended with that much more.  Stocks, bonds and inflation are also averaged
+// one at a time, to say which of them it was.  Inflation only matters through
+// nominal expenses, since returns are real.
+//
+// Contributions don't add up to the shortfall against the average sequence,
+// since years compound on each other, but they rank the years.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contribution {
+    pub year: usize, // Calendar year.
+    pub total: f64,
+    pub stocks: f64,
+    pub bonds: f64,
+    pub inflation: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribution {
+    pub start_year: usize,
+    pub ending_value: f64,
+    // Ending value with every year at the average, so with no sequence risk.
+    pub average_value: f64,
+    // Annual geometric means over the window, in percent.
+    pub average_stocks: f64,
+    pub average_bonds: f64,
+    pub average_inflation: f64,
+    pub contributions: Vec<Contribution>,
+}
+
+impl Attribution {
+    // How much worse the window did than the same average returns would have
+    // in every year.
+    pub fn shortfall(&self) -> f64 {
+        self.average_value - self.ending_value
+    }
+
+    // The n years whose returns did the most damage, worst first.
+    pub fn most_damaging(&self, n: usize) -> Vec<Contribution> {
+        let mut sorted = self.contributions.clone();
+        sorted.sort_by(|a, b| a.total.total_cmp(&b.total));
+        sorted
+            .into_iter()
+            .take(n)
+            .filter(|c| c.total < 0.)
+            .collect()
+    }
+}
+
+impl fmt::Display for Attribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Starting {}: ${:.3} M at the end, ${:.3} M with average years \
+             ({:.1}% stocks, {:.1}% bonds, {:.1}% inflation)",
+            self.start_year,
+            self.ending_value / 1e6,
+            self.average_value / 1e6,
+            self.average_stocks,
+            self.average_bonds,
+            self.average_inflation
+        )?;
+        for c in self.most_damaging(5) {
+            writeln!(
+                f,
+                "  {}: ${:.3} M (stocks ${:.3} M, bonds ${:.3} M, inflation ${:.3} M)",
+                c.year,
+                c.total / 1e6,
+                c.stocks / 1e6,
+                c.bonds / 1e6,
+                c.inflation / 1e6
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Backtest {
+    pub fn attribution(&self, stock_fractions: &[f64], year_offset: usize) -> Attribution {
+        let ppy = self.periods_per_year;
+        let years = self.real_expenses.len() / ppy;
+        assert!(years > 0);
+
+        // Annual geometric means over the window.
+        let mut growth = [1.; 3];
+        for i in 0..self.real_expenses.len() {
+            let index = self.index(year_offset, i);
+            growth[0] *= self.bond_return[index] + self.delta_return[index];
+            growth[1] *= self.bond_return[index];
+            growth[2] *= self.inflation[index];
+        }
+        let [stocks, bonds, inflation] = growth.map(|g| (g.powf(1. / years as f64) - 1.) * 100.);
+        let average = ShockYear {
+            stocks: Some(stocks),
+            bonds: Some(bonds),
+            inflation: Some(inflation),
+        };
+
+        let ending_value = self.single_run(stock_fractions, year_offset, false);
+        let run = |year: ShockYear, at: usize| {
+            self.shocked_run(
+                stock_fractions,
+                &Shock { years: vec![year] },
+                year_offset,
+                at,
+            )
+        };
+        let contributions = (0..years)
+            .map(|k| {
+                let only = |stocks, bonds, inflation| ShockYear {
+                    stocks,
+                    bonds,
+                    inflation,
+                };
+                Contribution {
+                    year: self.year_of(self.index(year_offset, k * ppy)),
+                    total: ending_value - run(average, k),
+                    stocks: ending_value - run(only(average.stocks, None, None), k),
+                    bonds: ending_value - run(only(None, average.bonds, None), k),
+                    inflation: ending_value - run(only(None, None, average.inflation), k),
+                }
+            })
+            .collect();
+        let average_value = self.shocked_run(
+            stock_fractions,
+            &Shock {
+                years: vec![average; years],
+            },
+            year_offset,
+            0,
+        );
+
+        Attribution {
+            start_year: self.year_of(year_offset),
+            ending_value,
+            average_value,
+            average_stocks: stocks,
+            average_bonds: bonds,
+            average_inflation: inflation,
+            contributions,
+        }
+    }
+
+    // Attribution of the worst window.
+    pub fn worst_attribution(&self, stock_fractions: &[f64]) -> Attribution {
+        let (offset, _, _, _) = self.worst_start(stock_fractions);
+        self.attribution(stock_fractions, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataset::Dataset;
+    use crate::Backtest;
+
+    #[test]
+    fn attribution() {
+        let dataset = Dataset::us_annual();
+        let backtest =
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![10_000.; 30], &dataset);
+        let fractions = [0.6; 30];
+        let attribution = backtest.worst_attribution(&fractions);
+        assert_eq!(attribution.start_year, backtest.worst_year(&fractions).0);
+        assert_eq!(attribution.contributions.len(), 30);
+        assert_eq!(attribution.contributions[0].year, attribution.start_year);
+        // The worst window is worst because of its order, not its average.
+        assert!(attribution.shortfall() > 0.);
+
+        // The damage is early: bad years just after retiring hurt most.
+        let worst = attribution.most_damaging(3);
+        assert_eq!(worst.len(), 3);
+        assert!(worst[0].total <= worst[1].total && worst[1].total <= worst[2].total);
+        assert!(worst.iter().all(|c| c.year < attribution.start_year + 15));
+        // With a 60% stock allocation, the damage is mostly stocks.
+        assert!(worst[0].stocks < worst[0].bonds);
+
+        assert!(attribution.average_stocks > attribution.average_bonds);
+        assert!(attribution.to_string().contains(&worst[0].year.to_string()));
+
+        // Without nominal expenses, inflation doesn't matter.
+        let real_only =
+            Backtest::from_dataset(1_000_000., vec![50_000.; 30], vec![0.; 30], &dataset);
+        let attribution = real_only.attribution(&fractions, 1966 - 1871);
+        assert!(attribution
+            .contributions
+            .iter()
+            .all(|c| c.inflation.abs() < 1e-6));
+    }
+}
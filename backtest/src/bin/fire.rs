@@ -0,0 +1,838 @@
+// The fire command line tool: run the analyses in the backtest library on a
+// scenario file.  See USAGE.
+
+use backtest::glide;
+use backtest::monte_carlo::{Bootstrap, ReturnGenerator};
+use backtest::parametric::Lognormal;
+use backtest::regime::RegimeSwitching;
+use backtest::report::Report;
+use backtest::scenario::{Allocation, Analysis, Scenario};
+use backtest::{percentile, Range};
+use std::fmt::Write;
+use std::process::exit;
+
+const USAGE: &str = "\
+usage: fire <command> <scenario.toml> [options]
+
+commands:
+  run        the analyses in the scenario's [analysis] run: success_rate,
+             worst_year, perfect_withdrawal, drawdown and swr_table, the
+             last over [analysis] horizons; also writes the HTML report
+             if [analysis] report is set
+  swr        historical safe withdrawal rate, and the perfect withdrawal
+             rate of every start year
+  optimize   search a glide path family for the best worst case
+             --family <name>        constant, linear or ramp; defaults to
+                                    the scenario's allocation family
+             --range <start:end:step>  one per family parameter
+  compare    the scenario's allocation against other glide paths, window
+             by window, with each one's wins and its differences from the
+             plan
+             --strategy <label=family:p1,p2,...>  may be repeated
+  stress     the scenario's allocation through bad starts (1929, 1966,
+             2000) and made up shocks in every window
+  simulate   Monte Carlo on made up histories
+             --model <name>         bootstrap, resampling history in
+                                    blocks, the default; lognormal or
+                                    student-t, fitted to history; or
+                                    regimes, switching between normal,
+                                    high inflation and crash years
+             --trials <n>           default 10000
+             --seed <n>             default 1
+             --block <years>        default 1, for bootstrap
+  report     write a self-contained HTML report of the plan
+             fire report <scenario.toml> [out.html]; the file defaults to
+             the scenario's [analysis] report
+
+options:
+  --format text|csv|json   default text; csv is the summary as key,value
+                           rows, then a blank line and the table
+  --min-success <rate>     the plan fails below this success rate;
+                           default 1 for history, 0.95 for simulate
+
+exit status: 0 if the plan succeeds, 1 if it fails, 2 for invalid input.";
+
+const PLAN_FAILS: i32 = 1;
+const INVALID_INPUT: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+enum Cell {
+    Number(f64),
+    Text(String),
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Number(x) => format!("{}", (x * 1e4).round() / 1e4),
+            Cell::Text(s) => s.clone(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Cell::Number(x) if x.is_finite() => format!("{}", x),
+            Cell::Number(_) => "null".to_string(),
+            Cell::Text(s) => json_string(s),
+        }
+    }
+}
+
+fn number(x: f64) -> Cell {
+    Cell::Number(x)
+}
+
+fn text(s: &str) -> Cell {
+    Cell::Text(s.to_string())
+}
+
+fn keyed(summary: Vec<(&str, Cell)>) -> Vec<(String, Cell)> {
+    summary
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+}
+
+fn names(columns: &[&str]) -> Vec<String> {
+    columns.iter().map(|c| c.to_string()).collect()
+}
+
+// What a command found: a few headline numbers and a table.
+struct Output {
+    summary: Vec<(String, Cell)>,
+    columns: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+    success_rate: f64,
+}
+
+impl Output {
+    fn render(&self, format: Format) -> String {
+        let mut out = String::new();
+        match format {
+            Format::Text => {
+                for (key, value) in &self.summary {
+                    writeln!(out, "{}: {}", key.replace('_', " "), value.text()).unwrap();
+                }
+                if !self.rows.is_empty() {
+                    writeln!(out).unwrap();
+                    let cells: Vec<Vec<String>> =
+                        std::iter::once(self.columns.iter().map(|c| c.replace('_', " ")).collect())
+                            .chain(
+                                self.rows
+                                    .iter()
+                                    .map(|row| row.iter().map(Cell::text).collect()),
+                            )
+                            .collect();
+                    let widths: Vec<usize> = (0..self.columns.len())
+                        .map(|j| cells.iter().map(|row| row[j].len()).max().unwrap())
+                        .collect();
+                    for row in cells {
+                        let padded: Vec<String> = row
+                            .iter()
+                            .zip(&widths)
+                            .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+                            .collect();
+                        writeln!(out, "{}", padded.join("  ")).unwrap();
+                    }
+                }
+            }
+            // The summary as key,value rows, then a blank line and the table.
+            Format::Csv => {
+                writeln!(out, "key,value").unwrap();
+                for (key, value) in &self.summary {
+                    writeln!(out, "{},{}", key, csv_field(&value.text())).unwrap();
+                }
+                if !self.rows.is_empty() {
+                    writeln!(out).unwrap();
+                    writeln!(out, "{}", self.columns.join(",")).unwrap();
+                    for row in &self.rows {
+                        let fields: Vec<String> =
+                            row.iter().map(|cell| csv_field(&cell.text())).collect();
+                        writeln!(out, "{}", fields.join(",")).unwrap();
+                    }
+                }
+            }
+            Format::Json => {
+                let mut fields: Vec<String> = self
+                    .summary
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", json_string(key), value.json()))
+                    .collect();
+                let rows: Vec<String> = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        let fields: Vec<String> = self
+                            .columns
+                            .iter()
+                            .zip(row)
+                            .map(|(column, cell)| {
+                                format!("{}: {}", json_string(column), cell.json())
+                            })
+                            .collect();
+                        format!("{{{}}}", fields.join(", "))
+                    })
+                    .collect();
+                fields.push(format!("\"rows\": [{}]", rows.join(",\n  ")));
+                writeln!(out, "{{\n{}\n}}", fields.join(",\n")).unwrap();
+            }
+        }
+        out
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// Options after the scenario file, as (name, value) pairs in order.
+struct Options(Vec<(String, String)>);
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument {}", arg))?;
+            let value = args
+                .next()
+                .ok_or_else(|| format!("--{} needs a value", name))?;
+            options.push((name.to_string(), value.clone()));
+        }
+        Ok(Options(options))
+    }
+
+    fn all(&self, name: &str) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.all(name).last().copied()
+    }
+
+    fn parse_or<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("bad value for --{}: {}", name, value)),
+        }
+    }
+
+    // Complain about options the command doesn't take.
+    fn check(&self, known: &[&str]) -> Result<(), String> {
+        match self.0.iter().find(|(n, _)| !known.contains(&n.as_str())) {
+            Some((name, _)) => Err(format!("unknown option --{}", name)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("{}", USAGE);
+        exit(INVALID_INPUT);
+    }
+    match run(&args[0], &args[1], &args[2..]) {
+        Ok((output, format, min_success)) => {
+            print!("{}", output.render(format));
+            if output.success_rate < min_success {
+                exit(PLAN_FAILS);
+            }
+        }
+        Err(e) => {
+            eprintln!("fire: {}", e);
+            exit(INVALID_INPUT);
+        }
+    }
+}
+
+fn run(command: &str, path: &str, args: &[String]) -> Result<(Output, Format, f64), String> {
+    // report takes the file to write before any options.
+    let (out, args) = match args.split_first() {
+        Some((out, rest)) if command == "report" && !out.starts_with("--") => {
+            (Some(out.as_str()), rest)
+        }
+        _ => (None, args),
+    };
+    let options = Options::parse(args)?;
+    let format = match options.get("format").unwrap_or("text") {
+        "text" => Format::Text,
+        "csv" => Format::Csv,
+        "json" => Format::Json,
+        other => return Err(format!("unknown format {}", other)),
+    };
+    let (extra, default_min): (&[&str], f64) = match command {
+        "run" => (&[], 1.),
+        "swr" => (&[], 1.),
+        "optimize" => (&["family", "range"], 1.),
+        "compare" => (&["strategy"], 1.),
+        "stress" => (&[], 1.),
+        "simulate" => (&["model", "trials", "seed", "block"], 0.95),
+        "report" => (&[], 1.),
+        _ => return Err(format!("unknown command {}\n\n{}", command, USAGE)),
+    };
+    options.check(&[&["format", "min-success"], extra].concat())?;
+
+    let scenario = Scenario::load(path)?;
+    let output = match command {
+        "run" => analyses(&scenario)?,
+        "swr" => swr(&scenario),
+        "optimize" => optimize(&scenario, &options)?,
+        "compare" => compare(&scenario, &options)?,
+        "stress" => stress(&scenario),
+        "report" => report(&scenario, out)?,
+        _ => simulate(&scenario, &options)?,
+    };
+    let min_success = options.parse_or("min-success", default_min)?;
+    Ok((output, format, min_success))
+}
+
+fn analyses(scenario: &Scenario) -> Result<Output, String> {
+    let backtest = scenario.backtest();
+    let fractions = scenario.stock_fractions();
+    let mut summary = keyed(vec![("scenario", text(&scenario.name))]);
+    let mut columns = Vec::new();
+    let mut rows = Vec::new();
+    for analysis in &scenario.analyses {
+        match analysis {
+            Analysis::SuccessRate => {
+                let stats = backtest.window_stats(&fractions);
+                summary.extend(keyed(vec![
+                    ("windows", number(stats.windows as f64)),
+                    ("success_rate", number(stats.success_rate * 100.)),
+                ]));
+            }
+            Analysis::WorstYear => {
+                let (year, value, second_year, second_value) = backtest.worst_year(&fractions);
+                summary.extend(keyed(vec![
+                    ("worst_start_year", number(year as f64)),
+                    ("worst_ending_value", number(value)),
+                    ("second_worst_start_year", number(second_year as f64)),
+                    ("second_worst_ending_value", number(second_value)),
+                ]));
+            }
+            Analysis::PerfectWithdrawal => {
+                let output = swr(scenario);
+                summary.extend(
+                    output
+                        .summary
+                        .into_iter()
+                        .filter(|(key, _)| key.contains("withdrawal_rate")),
+                );
+                // The SWR table, if asked for, has the rows.
+                if columns.is_empty() {
+                    columns = output.columns;
+                    rows = output.rows;
+                }
+            }
+            Analysis::Drawdown => {
+                let stats = backtest.drawdown_stats(&fractions);
+                summary.extend(keyed(vec![
+                    ("mean_max_drawdown", number(stats.mean_max_drawdown * 100.)),
+                    ("max_drawdown", number(stats.max_drawdown.1 * 100.)),
+                    (
+                        "max_drawdown_start_year",
+                        number(stats.max_drawdown.0 as f64),
+                    ),
+                    ("years_underwater", number(stats.years_underwater.1)),
+                    ("ulcer_index", number(stats.ulcer_index.1)),
+                    ("min_balance", number(stats.min_balance.1)),
+                ]));
+            }
+            Analysis::SwrTable => {
+                // At the plan's average allocation, as in the report.
+                let average = fractions.iter().sum::<f64>() / fractions.len() as f64;
+                let table = backtest.swr_table(average, &scenario.horizons);
+                summary.push(("swr_table_stocks".to_string(), number(average)));
+                columns = vec!["start_year".to_string()];
+                columns.extend(table.horizons.iter().map(|h| format!("swr_{}_years", h)));
+                rows = table
+                    .years
+                    .iter()
+                    .enumerate()
+                    .map(|(y, year)| {
+                        let mut row = vec![number(*year as f64)];
+                        row.extend(table.rates.iter().map(|rates| match rates[y] {
+                            Some(rate) => number(rate * 100.),
+                            None => text(""),
+                        }));
+                        row
+                    })
+                    .collect();
+            }
+        }
+    }
+    if let Some(path) = &scenario.report {
+        write_report(scenario, path)?;
+        summary.push(("report".to_string(), text(path)));
+    }
+    Ok(Output {
+        summary,
+        columns,
+        rows,
+        success_rate: backtest.success_rate(&fractions),
+    })
+}
+
+fn swr(scenario: &Scenario) -> Output {
+    let backtest = scenario.backtest();
+    let fractions = scenario.stock_fractions();
+    let rates = backtest.perfect_withdrawal_rates(&fractions, scenario.legacy);
+    let (worst_year, swr) = rates
+        .iter()
+        .copied()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+    let values: Vec<f64> = rates.iter().map(|(_, rate)| *rate).collect();
+    Output {
+        summary: keyed(vec![
+            ("scenario", text(&scenario.name)),
+            ("safe_withdrawal_rate", number(swr * 100.)),
+            ("worst_start_year", number(worst_year as f64)),
+            (
+                "median_withdrawal_rate",
+                number(percentile(&values, 0.5) * 100.),
+            ),
+            (
+                "success_rate",
+                number(backtest.success_rate(&fractions) * 100.),
+            ),
+        ]),
+        columns: names(&["start_year", "perfect_withdrawal_rate"]),
+        rows: rates
+            .iter()
+            .map(|(year, rate)| vec![number(*year as f64), number(rate * 100.)])
+            .collect(),
+        success_rate: backtest.success_rate(&fractions),
+    }
+}
+
+fn optimize(scenario: &Scenario, options: &Options) -> Result<Output, String> {
+    if scenario.dataset.periods_per_year != 1 {
+        return Err("optimize needs annual data".to_string());
+    }
+    let name = match (options.get("family"), &scenario.allocation) {
+        (Some(name), _) => name.to_string(),
+        (None, Allocation::Family { name, .. }) => name.clone(),
+        (None, Allocation::Fractions(_)) => return Err("--family is needed".to_string()),
+    };
+    let (family, params) =
+        glide::family(&name).ok_or_else(|| format!("unknown glide path family {}", name))?;
+    let bounds = options
+        .all("range")
+        .iter()
+        .map(|range| {
+            let parts: Vec<f64> = range
+                .split(':')
+                .map(|x| x.parse().map_err(|_| format!("bad range {}", range)))
+                .collect::<Result<_, _>>()?;
+            match parts[..] {
+                [start, end, step] if step > 0. && start <= end => Ok((start, end, step)),
+                _ => Err(format!("range {} should be start:end:step", range)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if bounds.len() != params.len() {
+        return Err(format!(
+            "family {} needs a --range for each of: {}",
+            name,
+            params.join(", ")
+        ));
+    }
+    // Every family interpolates between its parameters, so the fractions at
+    // the corners of the grid bound the rest.
+    for corner in 0..1 << bounds.len() {
+        let values: Vec<f64> = bounds
+            .iter()
+            .enumerate()
+            .map(|(i, (start, end, _))| if corner >> i & 1 == 0 { *start } else { *end })
+            .collect();
+        if family(&values, scenario.years)
+            .iter()
+            .any(|f| !(0. ..=1.).contains(f))
+        {
+            return Err(format!(
+                "--range {} gives stock fractions outside [0, 1]",
+                options.all("range").join(" ")
+            ));
+        }
+    }
+
+    let ranges: Vec<Range> = bounds
+        .iter()
+        .map(|(start, end, step)| Range::new(*start, *end, *step))
+        .collect();
+
+    let backtest = scenario.backtest();
+    let (values, worst_value, worst_year, second_value, second_year) =
+        backtest.best_fractions(&ranges, family, scenario.years);
+    let fractions = family(&values, scenario.years);
+    let mut summary = keyed(vec![("family", text(&name))]);
+    for (param, value) in params.iter().zip(&values) {
+        summary.push((param.to_string(), number(*value)));
+    }
+    summary.extend(keyed(vec![
+        ("worst_ending_value", number(worst_value)),
+        ("worst_start_year", number(worst_year as f64)),
+        ("second_worst_ending_value", number(second_value)),
+        ("second_worst_start_year", number(second_year as f64)),
+    ]));
+    Ok(Output {
+        summary,
+        columns: names(&["year", "stocks"]),
+        rows: fractions
+            .iter()
+            .enumerate()
+            .map(|(i, f)| vec![number(i as f64), number(*f)])
+            .collect(),
+        success_rate: backtest.success_rate(&fractions),
+    })
+}
+
+// "label=family:p1,p2" to a label and its glide path.
+fn strategy(spec: &str, years: usize) -> Result<(String, Vec<f64>), String> {
+    let bad = || format!("strategy {} should be label=family:p1,p2,...", spec);
+    let (label, rest) = spec.split_once('=').ok_or_else(bad)?;
+    let (name, params) = rest.split_once(':').ok_or_else(bad)?;
+    let params: Vec<f64> = params
+        .split(',')
+        .map(|p| p.trim().parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    let (family, names) =
+        glide::family(name).ok_or_else(|| format!("unknown glide path family {}", name))?;
+    if params.len() != names.len() {
+        return Err(format!(
+            "family {} takes {} params: {}",
+            name,
+            names.len(),
+            names.join(", ")
+        ));
+    }
+    let fractions = family(&params, years);
+    if fractions.iter().any(|f| !(0. ..=1.).contains(f)) {
+        return Err(format!(
+            "strategy {} has stock fractions outside [0, 1]",
+            label
+        ));
+    }
+    Ok((label.to_string(), fractions))
+}
+
+// Annual stock fractions for every period of the scenario's data.
+fn per_period(scenario: &Scenario, annual: &[f64]) -> Vec<f64> {
+    annual
+        .iter()
+        .flat_map(|f| std::iter::repeat_n(*f, scenario.dataset.periods_per_year))
+        .collect()
+}
+
+fn compare(scenario: &Scenario, options: &Options) -> Result<Output, String> {
+    let mut strategies = vec![("plan".to_string(), scenario.stock_fractions())];
+    for spec in options.all("strategy") {
+        let (label, annual) = strategy(spec, scenario.years)?;
+        strategies.push((label, per_period(scenario, &annual)));
+    }
+
+    let backtest = scenario.backtest();
+    let strategies: Vec<(&str, &[f64])> = strategies
+        .iter()
+        .map(|(label, fractions)| (label.as_str(), fractions.as_slice()))
+        .collect();
+    let comparison = backtest.compare(&strategies);
+    let wins = comparison.wins();
+
+    // Each strategy's record, and how it did against the plan window by
+    // window.
+    let mut summary = keyed(vec![
+        ("scenario", text(&scenario.name)),
+        ("windows", number(comparison.years.len() as f64)),
+    ]);
+    for (i, (label, fractions)) in strategies.iter().enumerate() {
+        let stats = backtest.window_stats(fractions);
+        summary.push((
+            format!("{}_success_rate", label),
+            number(stats.success_rate * 100.),
+        ));
+        summary.push((
+            format!("{}_worst_ending_value", label),
+            number(stats.worst_value),
+        ));
+        summary.push((format!("{}_windows_won", label), number(wins[i] as f64)));
+        if i > 0 {
+            let paired = comparison.paired(i, 0);
+            for (key, value) in [
+                ("beat_plan", paired.wins as f64),
+                ("lost_to_plan", paired.losses as f64),
+                ("minus_plan_mean", paired.mean),
+                ("minus_plan_min", paired.min),
+                ("minus_plan_median", paired.median),
+                ("minus_plan_max", paired.max),
+            ] {
+                summary.push((format!("{}_{}", label, key), number(value)));
+            }
+        }
+    }
+
+    let mut columns = vec!["start_year".to_string()];
+    columns.extend(comparison.labels.iter().cloned());
+    columns.push("winner".to_string());
+    let winners = comparison.winners();
+    let rows = comparison
+        .years
+        .iter()
+        .zip(&comparison.ending_values)
+        .zip(winners)
+        .map(|((year, values), winner)| {
+            let mut row = vec![number(*year as f64)];
+            row.extend(values.iter().map(|v| number(*v)));
+            row.push(text(&comparison.labels[winner]));
+            row
+        })
+        .collect();
+    Ok(Output {
+        summary,
+        columns,
+        rows,
+        success_rate: backtest.success_rate(strategies[0].1),
+    })
+}
+
+fn stress(scenario: &Scenario) -> Output {
+    let results = scenario
+        .backtest()
+        .stress_tests(&scenario.stock_fractions());
+    Output {
+        summary: keyed(vec![("scenario", text(&scenario.name))]),
+        columns: names(&[
+            "stress",
+            "windows",
+            "years",
+            "success_rate",
+            "worst_start_year",
+            "worst_ending_value",
+        ]),
+        rows: results
+            .iter()
+            .map(|r| {
+                vec![
+                    text(&r.name),
+                    number(r.windows as f64),
+                    number(r.years as f64),
+                    number(r.success_rate * 100.),
+                    number(r.worst_year as f64),
+                    number(r.worst_value),
+                ]
+            })
+            .collect(),
+        // The plan fails if it fails any stress test.
+        success_rate: results.iter().map(|r| r.success_rate).fold(1., f64::min),
+    }
+}
+
+fn simulate(scenario: &Scenario, options: &Options) -> Result<Output, String> {
+    let trials: usize = options.parse_or("trials", 10_000)?;
+    let seed: u64 = options.parse_or("seed", 1)?;
+    let block: usize = options.parse_or("block", 1)?;
+    if trials == 0 || block == 0 {
+        return Err("--trials and --block must be at least 1".to_string());
+    }
+
+    let model = options.get("model").unwrap_or("bootstrap");
+    let dataset = &scenario.dataset;
+    let generator: Box<dyn ReturnGenerator> = match model {
+        "bootstrap" => Box::new(Bootstrap::new(dataset, block)),
+        "lognormal" => Box::new(Lognormal::fit(dataset)?),
+        "student-t" => Box::new(Lognormal::fit(dataset)?.student_t(5)),
+        "regimes" => Box::new(
+            RegimeSwitching::fit(dataset, -15., 6.)
+                .map_err(|e| format!("--model regimes: {}", e))?,
+        ),
+        _ => return Err(format!("unknown model {}", model)),
+    };
+
+    let backtest = scenario.backtest();
+    let simulation = backtest.simulate(&*generator, &scenario.stock_fractions(), trials, seed);
+    Ok(Output {
+        summary: keyed(vec![
+            ("scenario", text(&scenario.name)),
+            ("model", text(model)),
+            ("trials", number(trials as f64)),
+            ("seed", number(seed as f64)),
+            ("block_years", number(block as f64)),
+            ("success_rate", number(simulation.success_rate() * 100.)),
+        ]),
+        columns: names(&["percentile", "ending_value"]),
+        rows: [5, 10, 25, 50, 75, 90, 95]
+            .iter()
+            .map(|p| {
+                vec![
+                    number(*p as f64),
+                    number(simulation.percentile(*p as f64 / 100.)),
+                ]
+            })
+            .collect(),
+        success_rate: simulation.success_rate(),
+    })
+}
+
+fn report(scenario: &Scenario, out: Option<&str>) -> Result<Output, String> {
+    let path = out
+        .map(str::to_string)
+        .or_else(|| scenario.report.clone())
+        .ok_or("report needs a file to write, or [analysis] report in the scenario")?;
+    write_report(scenario, &path)?;
+    let backtest = scenario.backtest();
+    let fractions = scenario.stock_fractions();
+    Ok(Output {
+        summary: keyed(vec![
+            ("scenario", text(&scenario.name)),
+            ("report", text(&path)),
+            (
+                "success_rate",
+                number(backtest.success_rate(&fractions) * 100.),
+            ),
+        ]),
+        columns: Vec::new(),
+        rows: Vec::new(),
+        success_rate: backtest.success_rate(&fractions),
+    })
+}
+
+fn write_report(scenario: &Scenario, path: &str) -> Result<(), String> {
+    let backtest = scenario.backtest();
+    let fractions = scenario.stock_fractions();
+    Report::new(&scenario.name, &scenario.dataset, &backtest, &fractions)
+        .write(path)
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keyed, names, number, strategy, text, Format, Options, Output};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn options() {
+        let options = Options::parse(&args(&[
+            "--strategy",
+            "a=constant:0.5",
+            "--format",
+            "csv",
+            "--strategy",
+            "b=linear:0.8,0.4",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.all("strategy"),
+            ["a=constant:0.5", "b=linear:0.8,0.4"]
+        );
+        assert_eq!(options.get("format"), Some("csv"));
+        assert_eq!(options.parse_or("trials", 10), Ok(10));
+        assert!(options.check(&["format", "strategy"]).is_ok());
+        assert_eq!(
+            options.check(&["format"]),
+            Err("unknown option --strategy".to_string())
+        );
+
+        assert!(Options::parse(&args(&["--trials"])).is_err());
+        assert!(Options::parse(&args(&["trials", "5"])).is_err());
+        let bad = Options::parse(&args(&["--trials", "many"])).unwrap();
+        assert!(bad.parse_or("trials", 10usize).is_err());
+
+        assert_eq!(strategy("a=constant:0.5", 3).unwrap().1, [0.5; 3]);
+        assert!(strategy("a=constant:1.5", 3).is_err());
+        assert!(strategy("a=linear:0.5", 3).is_err());
+        assert!(strategy("constant:0.5", 3).is_err());
+    }
+
+    fn output() -> Output {
+        Output {
+            summary: keyed(vec![
+                ("scenario", text("4%, 60/40")),
+                ("success_rate", number(95.123456)),
+            ]),
+            columns: names(&["start_year", "ending_value"]),
+            rows: vec![
+                vec![number(1966.), number(95_819.2)],
+                vec![number(1929.), number(f64::NAN)],
+            ],
+            success_rate: 0.95,
+        }
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            output().render(Format::Csv),
+            "key,value\n\
+             scenario,\"4%, 60/40\"\n\
+             success_rate,95.1235\n\
+             \n\
+             start_year,ending_value\n\
+             1966,95819.2\n\
+             1929,NaN\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            output().render(Format::Json),
+            "{\n\
+             \"scenario\": \"4%, 60/40\",\n\
+             \"success_rate\": 95.123456,\n\
+             \"rows\": [{\"start_year\": 1966, \"ending_value\": 95819.2},\n  \
+             {\"start_year\": 1929, \"ending_value\": null}]\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn text_table() {
+        assert_eq!(
+            output().render(Format::Text),
+            "scenario: 4%, 60/40\n\
+             success rate: 95.1235\n\
+             \n\
+             start year  ending value\n      \
+             1966       95819.2\n      \
+             1929           NaN\n"
+        );
+    }
+}
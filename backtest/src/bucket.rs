@@ -0,0 +1,226 @@
+use crate::annuity::Purchased;
+use crate::time_series;
+use crate::Backtest;
+
+// The bucket approach: spend from a cash bucket holding a few years of
+// expenses, behind it a bond bucket, with the rest in stocks.  At the start of
+// each year the cash bucket is refilled from stocks if stocks had a good year,
+// and from bonds otherwise.
+pub struct BucketStrategy {
+    pub cash_years: f64, // Cash bucket target, in years of the coming year's expenses.
+    pub bond_years: f64, // Bond bucket target.  0 for a two bucket approach.
+    pub up_year: f64,    // Real stock return, in percent, that counts as an up year.
+    pub refill_bonds: bool, // After up years, also top up the bond bucket from stocks.
+    cash_return: Vec<f64>,
+}
+
+impl BucketStrategy {
+    // Cash earns `cash`, a real return in percent per period aligned with the
+    // backtest's dataset.
+    pub fn new(cash_years: f64, bond_years: f64, cash: Vec<f64>) -> Self {
+        assert!(cash_years >= 0. && bond_years >= 0.);
+        BucketStrategy {
+            cash_years,
+            bond_years,
+            up_year: 0.,
+            refill_bonds: true,
+            cash_return: cash.iter().map(|r| 1. + r / 100.).collect(),
+        }
+    }
+
+    // The cash bucket held in short term treasuries, for the US data.
+    // time_series has no cash or T-bill series, and these are notes of a few
+    // years, so the bucket is less safe than cash would be.
+    pub fn in_short_term_treasuries(cash_years: f64, bond_years: f64) -> Self {
+        Self::new(
+            cash_years,
+            bond_years,
+            time_series::SHORT_TERM_TREASURIES.to_vec(),
+        )
+    }
+}
+
+// Bucket balances at the end of a year, in real dollars.
+#[derive(Clone, Copy, Debug)]
+pub struct BucketYear {
+    pub year: usize,
+    pub expenses: f64, // Less annuity income.
+    pub cash: f64,
+    pub bonds: f64,
+    pub stocks: f64,
+}
+
+impl BucketYear {
+    pub fn total(&self) -> f64 {
+        self.cash + self.bonds + self.stocks
+    }
+}
+
+// Move money from `from` until `to` reaches `target`, never taking `from`
+// below zero.
+fn fill(from: &mut f64, to: &mut f64, target: f64) {
+    let amount = (target - *to).min(*from).max(0.);
+    *from -= amount;
+    *to += amount;
+}
+
+impl Backtest {
+    pub fn bucket_run(
+        &self,
+        strategy: &BucketStrategy,
+        year_offset: usize,
+        verbose: bool,
+    ) -> Vec<BucketYear> {
+        assert_eq!(strategy.cash_return.len(), self.bond_return.len());
+
+        let mut cash = 0.;
+        let mut bonds = 0.;
+        let mut stocks = self.start_portfolio;
+        let mut up_year = true; // Fill the buckets from the initial portfolio.
+        let mut purchased: Vec<Option<Purchased>> = self.annuities.iter().map(|_| None).collect();
+
+        let mut years = Vec::new();
+        let mut inflation_factor = 1.0;
+        for i in 0..self.real_expenses.len() {
+            let index = self.index(year_offset, i);
+
+            // Annuities are bought from every bucket alike, leaving the
+            // buckets in the same proportions.
+            let before = cash + bonds + stocks;
+            let mut after = before;
+            let income =
+                self.annuity_income(&mut purchased, i, year_offset, &mut after, inflation_factor);
+            if before > 0. {
+                for bucket in [&mut cash, &mut bonds, &mut stocks] {
+                    *bucket *= after / before;
+                }
+            }
+            let expenses = self.nominal_expenses[i] / inflation_factor
+                + self.real_expenses[i]
+                + self.event_expenses(i, inflation_factor)
+                - income;
+
+            // Refill, then remove expenses at the start of the year.
+            let cash_target = strategy.cash_years * expenses;
+            let bond_target = strategy.bond_years * expenses;
+            if up_year {
+                fill(&mut stocks, &mut cash, cash_target);
+                if strategy.refill_bonds {
+                    fill(&mut stocks, &mut bonds, bond_target);
+                }
+            } else {
+                fill(&mut bonds, &mut cash, cash_target);
+            }
+
+            // If the cash runs out, sell bonds before stocks.  Once everything
+            // is gone, the deficit is carried as negative cash.
+            fill(&mut bonds, &mut cash, expenses);
+            fill(&mut stocks, &mut cash, expenses);
+            cash -= expenses;
+
+            // A year passes.
+            let stock_return = self.bond_return[index] + self.delta_return[index];
+            cash *= strategy.cash_return[index];
+            bonds *= self.bond_return[index];
+            stocks *= stock_return;
+            up_year = (stock_return - 1.) * 100. >= strategy.up_year;
+
+            inflation_factor *= self.inflation[index];
+            let year = BucketYear {
+                year: self.year_of(index),
+                expenses,
+                cash,
+                bonds,
+                stocks,
+            };
+            if verbose {
+                println!(
+                    "{}: expenses ${:.1}k, cash ${:.1}k, bonds ${:.1}k, stocks ${:.1}k, total ${:.3}k{}",
+                    self.label(index),
+                    expenses / 1e3,
+                    cash / 1e3,
+                    bonds / 1e3,
+                    stocks / 1e3,
+                    year.total() / 1e3,
+                    self.event_labels(i)
+                );
+            }
+            years.push(year);
+        }
+        years
+    }
+
+    // Like worst_year, for the bucket strategy.
+    pub fn worst_year_buckets(&self, strategy: &BucketStrategy) -> (usize, f64, usize, f64) {
+        let (worst_year, worst_value, second_worst_year, second_worst_value) =
+            self.worst_offsets(|year_offset| {
+                let years = self.bucket_run(strategy, year_offset, false);
+                Some(years.last().map_or(self.start_portfolio, |y| y.total()))
+            });
+        (
+            self.year_of(worst_year),
+            worst_value,
+            self.year_of(second_worst_year),
+            second_worst_value,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BucketStrategy;
+    use crate::annuity::{Annuity, Mortality, Payout};
+    use crate::time_series;
+    use crate::Backtest;
+
+    fn backtest() -> Backtest {
+        Backtest::new(
+            1000.,
+            vec![40.; 5],
+            vec![0.; 5],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+    }
+
+    #[test]
+    fn no_buckets_is_all_stocks() {
+        let backtest = backtest();
+        let strategy = BucketStrategy::in_short_term_treasuries(0., 0.);
+        for year_offset in [0, 60, 140] {
+            let years = backtest.bucket_run(&strategy, year_offset, false);
+            let expected = backtest.single_run(&[1.; 5], year_offset, false);
+            assert!((years.last().unwrap().total() - expected).abs() < 1e-6);
+        }
+
+        // Annuity income pays part of the expenses, as it does in run.
+        let annuity = Annuity::new(
+            0.5,
+            2,
+            65,
+            Payout::Real,
+            Mortality::gompertz(88., 10.),
+            time_series::SHORT_TERM_TREASURIES.to_vec(),
+        );
+        let annuitized = backtest.with_annuity(annuity);
+        let years = annuitized.bucket_run(&strategy, 60, false);
+        let expected = annuitized.single_run(&[1.; 5], 60, false);
+        assert!((years.last().unwrap().total() - expected).abs() < 1e-6);
+        assert!(years[2].expenses < years[1].expenses);
+    }
+
+    #[test]
+    fn refills_from_bonds_after_down_years() {
+        // Stocks fell in 1929, 1930 and 1931, so after the first year all
+        // refills come from bonds and stocks are left alone.
+        let strategy = BucketStrategy::in_short_term_treasuries(2., 3.);
+        let years = backtest().bucket_run(&strategy, 1929 - time_series::FIRST_YEAR, false);
+        let mut stocks = 1000. - 5. * 40.;
+        for (i, year) in years.iter().take(3).enumerate() {
+            stocks *=
+                1. + time_series::TOTAL_STOCK_MARKET[1929 - time_series::FIRST_YEAR + i] / 100.;
+            assert!((year.stocks - stocks).abs() < 1e-6);
+        }
+        assert_eq!(years[0].year, 1929);
+    }
+}
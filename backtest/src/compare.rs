@@ -0,0 +1,140 @@
+use crate::{percentile, Backtest};
+
+// Several glide paths over the same historical windows.  Since every
+// strategy sees the same sequence of returns in a given window, the
+// difference between two strategies' ending values in each window is a
+// paired comparison, which says much more than comparing their worst years.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub labels: Vec<String>,
+    pub years: Vec<usize>, // Start year of each window.
+    // ending_values[window][strategy]
+    pub ending_values: Vec<Vec<f64>>,
+}
+
+// Summary of the paired differences a - b over all windows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Differences {
+    pub wins: usize, // Windows where a ended with more.
+    pub losses: usize,
+    pub ties: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub p10: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub max: f64,
+}
+
+impl Comparison {
+    // Index of the strategy with the largest ending value in each window.
+    // Ties go to the first.
+    pub fn winners(&self) -> Vec<usize> {
+        self.ending_values
+            .iter()
+            .map(|values| {
+                (0..values.len())
+                    .reduce(|best, i| if values[i] > values[best] { i } else { best })
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    // How many windows each strategy won.
+    pub fn wins(&self) -> Vec<usize> {
+        let mut wins = vec![0; self.labels.len()];
+        for winner in self.winners() {
+            wins[winner] += 1;
+        }
+        wins
+    }
+
+    // Strategy a's ending value minus b's, in each window.
+    pub fn differences(&self, a: usize, b: usize) -> Vec<f64> {
+        self.ending_values
+            .iter()
+            .map(|values| values[a] - values[b])
+            .collect()
+    }
+
+    pub fn paired(&self, a: usize, b: usize) -> Differences {
+        let differences = self.differences(a, b);
+        let count = |f: fn(&f64) -> bool| differences.iter().filter(|d| f(d)).count();
+        Differences {
+            wins: count(|d| *d > 0.),
+            losses: count(|d| *d < 0.),
+            ties: count(|d| *d == 0.),
+            mean: differences.iter().sum::<f64>() / differences.len() as f64,
+            min: percentile(&differences, 0.),
+            p10: percentile(&differences, 0.1),
+            median: percentile(&differences, 0.5),
+            p90: percentile(&differences, 0.9),
+            max: percentile(&differences, 1.),
+        }
+    }
+}
+
+impl Backtest {
+    // Run each labelled glide path over every historical window.
+    pub fn compare(&self, strategies: &[(&str, &[f64])]) -> Comparison {
+        assert!(!strategies.is_empty());
+        let runs: Vec<Vec<(usize, f64)>> = strategies
+            .iter()
+            .map(|(_, fractions)| self.ending_values(fractions))
+            .collect();
+        Comparison {
+            labels: strategies.iter().map(|(l, _)| l.to_string()).collect(),
+            years: runs[0]
+                .iter()
+                .map(|(offset, _)| self.year_of(*offset))
+                .collect(),
+            ending_values: (0..runs[0].len())
+                .map(|w| runs.iter().map(|run| run[w].1).collect())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::time_series;
+    use crate::Backtest;
+
+    #[test]
+    fn fixed_vs_tent() {
+        let backtest = Backtest::new(
+            1000.,
+            vec![40.; 30],
+            vec![0.; 30],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        );
+        let fixed = [0.6; 30];
+        let tent: Vec<f64> = (0..30).map(|i| (0.3 + i as f64 * 0.03).min(0.7)).collect();
+        let bonds = [0.; 30];
+        let comparison = backtest.compare(&[("fixed", &fixed), ("tent", &tent), ("bonds", &bonds)]);
+
+        let windows = time_series::YEARS - 30 + 1;
+        assert_eq!(comparison.years.len(), windows);
+        assert_eq!(comparison.years[95], 1966);
+        assert_eq!(
+            comparison.ending_values[95][1],
+            backtest.single_run(&tent, 95, false)
+        );
+        assert_eq!(comparison.wins().iter().sum::<usize>(), windows);
+
+        let paired = comparison.paired(0, 1);
+        assert_eq!(paired.wins + paired.losses + paired.ties, windows);
+        let differences = comparison.differences(0, 1);
+        assert_eq!(paired.wins, differences.iter().filter(|d| **d > 0.).count());
+        assert!(paired.min <= paired.p10 && paired.p10 <= paired.median);
+        assert!(paired.median <= paired.p90 && paired.p90 <= paired.max);
+        // Differences are antisymmetric.
+        let reversed = comparison.paired(1, 0);
+        assert_eq!(
+            (reversed.wins, reversed.losses),
+            (paired.losses, paired.wins)
+        );
+        assert!((reversed.mean + paired.mean).abs() < 1e-9);
+    }
+}
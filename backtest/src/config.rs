@@ -0,0 +1,601 @@
+// The subset of TOML that scenario files need, parsed without any outside
+// crates.  Exactly this is accepted:
+//
+//   - # comments, to the end of the line
+//   - [table] and [[array of tables]] headers, with dotted names such as
+//     [a.b]; each table once
+//   - key = value, one to a line, with bare keys or "quoted" ones
+//   - basic "strings", with the escapes \n, \t, \" and \\
+//   - finite decimal numbers, with an optional sign, fraction and exponent,
+//     and underscores between digits, such as 1_000_000 or -2.5e-3
+//   - true and false
+//   - arrays of values, which may span lines and end with a comma
+//   - inline { tables }, on one line
+//
+// Anything else is an error rather than a guess: dotted keys, 'literal' and
+// multi-line strings, dates and times, inf, nan, hex, octal and binary
+// numbers, and headers that redefine a table or add to an inline table or
+// to an array given as a value.
+
+use std::collections::{BTreeMap, HashSet};
+
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Number(_) => "a number",
+            Value::Bool(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Table, String> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+        headers: HashSet::new(),
+        values: HashSet::new(),
+    };
+    parser
+        .document()
+        .map_err(|e| format!("line {}: {}", parser.line, e))
+}
+
+// Typed lookups, with errors that name the key.
+pub fn get_str<'a>(table: &'a Table, key: &str) -> Result<Option<&'a str>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(v) => Err(format!("{} should be a string, not {}", key, v.kind())),
+    }
+}
+
+pub fn get_f64(table: &Table, key: &str) -> Result<Option<f64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Number(x)) => Ok(Some(*x)),
+        Some(v) => Err(format!("{} should be a number, not {}", key, v.kind())),
+    }
+}
+
+pub fn get_usize(table: &Table, key: &str) -> Result<Option<usize>, String> {
+    match get_f64(table, key)? {
+        Some(x) if x < 0. || x.fract() != 0. => Err(format!(
+            "{} should be a whole number of at least 0, not {}",
+            key, x
+        )),
+        x => Ok(x.map(|x| x as usize)),
+    }
+}
+
+pub fn get_bool(table: &Table, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Bool(b)) => Ok(Some(*b)),
+        Some(v) => Err(format!("{} should be true or false, not {}", key, v.kind())),
+    }
+}
+
+pub fn get_table<'a>(table: &'a Table, key: &str) -> Result<Option<&'a Table>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Table(t)) => Ok(Some(t)),
+        Some(v) => Err(format!("{} should be a table, not {}", key, v.kind())),
+    }
+}
+
+pub fn get_array<'a>(table: &'a Table, key: &str) -> Result<Option<&'a [Value]>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Array(a)) => Ok(Some(a)),
+        Some(v) => Err(format!("{} should be an array, not {}", key, v.kind())),
+    }
+}
+
+pub fn get_numbers(table: &Table, key: &str) -> Result<Option<Vec<f64>>, String> {
+    let Some(array) = get_array(table, key)? else {
+        return Ok(None);
+    };
+    array
+        .iter()
+        .map(|v| match v {
+            Value::Number(x) => Ok(*x),
+            v => Err(format!("{} should hold numbers, not {}", key, v.kind())),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+// Like get_numbers, but every number must be a whole number of at least 0.
+pub fn get_usizes(table: &Table, key: &str) -> Result<Option<Vec<usize>>, String> {
+    let Some(numbers) = get_numbers(table, key)? else {
+        return Ok(None);
+    };
+    numbers
+        .iter()
+        .map(|&x| {
+            if x < 0. || x.fract() != 0. {
+                Err(format!(
+                    "{} should hold whole numbers of at least 0, not {}",
+                    key, x
+                ))
+            } else {
+                Ok(x as usize)
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+pub fn get_strings<'a>(table: &'a Table, key: &str) -> Result<Option<Vec<&'a str>>, String> {
+    let Some(array) = get_array(table, key)? else {
+        return Ok(None);
+    };
+    array
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => Ok(s.as_str()),
+            v => Err(format!("{} should hold strings, not {}", key, v.kind())),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+// Tables in an array of tables, from [[key]].
+pub fn get_tables<'a>(table: &'a Table, key: &str) -> Result<Vec<&'a Table>, String> {
+    let Some(array) = get_array(table, key)? else {
+        return Ok(Vec::new());
+    };
+    array
+        .iter()
+        .map(|v| match v {
+            Value::Table(t) => Ok(t),
+            v => Err(format!("{} should hold tables, not {}", key, v.kind())),
+        })
+        .collect()
+}
+
+// Complain about keys that aren't in `known`, which are usually typos.
+pub fn check_keys(table: &Table, context: &str, known: &[&str]) -> Result<(), String> {
+    match table.keys().find(|k| !known.contains(&k.as_str())) {
+        Some(key) => Err(format!("unknown key {} in {}", key, context)),
+        None => Ok(()),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    // Tables with a [header], and tables and arrays given as values, by
+    // where_is, so later headers can't reopen them.
+    headers: HashSet<String>,
+    values: HashSet<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!(
+                "expected '{}', found the end of the file",
+                expected
+            )),
+        }
+    }
+
+    // Spaces and tabs, and a comment to the end of the line.
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '#' => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    // Whitespace including newlines, as allowed inside arrays.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_space();
+            if self.peek() == Some('\n') {
+                self.next();
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_space();
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(format!("unexpected '{}' after value", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        // Path to the table that keys currently go in.
+        let mut path: Vec<String> = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.next();
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.next();
+                    }
+                    path = self.table_name()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    for end in 1..=path.len() {
+                        if self.values.contains(&where_is(&root, &path[..end])) {
+                            return Err(format!(
+                                "{} was given as a value, so a header can't add to it",
+                                path[..end].join(".")
+                            ));
+                        }
+                    }
+                    open_table(&mut root, &path, array)?;
+                    if !self.headers.insert(where_is(&root, &path)) {
+                        return Err(format!("{} is defined twice", path.join(".")));
+                    }
+                    self.end_of_line()?;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_space();
+                    self.expect('=')?;
+                    self.skip_space();
+                    let value = self.value()?;
+                    let nested = matches!(value, Value::Table(_) | Value::Array(_));
+                    let table = current_table(&mut root, &path)?;
+                    if table.insert(key.clone(), value).is_some() {
+                        return Err(format!("{} is defined twice", key));
+                    }
+                    if nested {
+                        let path = [&path[..], &[key]].concat();
+                        self.values.insert(where_is(&root, &path));
+                    }
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    fn table_name(&mut self) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_space();
+            path.push(self.key()?);
+            self.skip_space();
+            if self.peek() == Some('.') {
+                self.next();
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(match self.peek() {
+                Some(c) => format!("expected a key, found '{}'", c),
+                None => "expected a key".to_string(),
+            });
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => {
+                self.next();
+                let mut array = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.next();
+                        return Ok(Value::Array(array));
+                    }
+                    array.push(self.value()?);
+                    self.skip_blank();
+                    match self.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(array)),
+                        _ => return Err("expected ',' or ']' in array".to_string()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.next();
+                let mut table = Table::new();
+                loop {
+                    self.skip_space();
+                    if self.peek() == Some('}') && table.is_empty() {
+                        self.next();
+                        return Ok(Value::Table(table));
+                    }
+                    let key = self.key()?;
+                    self.skip_space();
+                    self.expect('=')?;
+                    self.skip_space();
+                    let value = self.value()?;
+                    if table.insert(key.clone(), value).is_some() {
+                        return Err(format!("{} is defined twice", key));
+                    }
+                    self.skip_space();
+                    match self.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Value::Table(table)),
+                        _ => return Err("expected ',' or '}' in inline table".to_string()),
+                    }
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-._".contains(c))
+                {
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => Err("expected a value".to_string()),
+                    _ => number(&word).map(Value::Number),
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    _ => return Err("unknown escape in string".to_string()),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
+
+// A decimal number, finite, with underscores and any point between digits.
+fn number(word: &str) -> Result<f64, String> {
+    let chars: Vec<char> = word.chars().collect();
+    let digit =
+        |i: Option<usize>| matches!(i.and_then(|i| chars.get(i)), Some(c) if c.is_ascii_digit());
+    let between_digits = chars
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c == '_' || **c == '.')
+        .all(|(i, _)| digit(i.checked_sub(1)) && digit(Some(i + 1)));
+    match word.replace('_', "").parse::<f64>() {
+        Ok(x) if between_digits && x.is_finite() => Ok(x),
+        Ok(_) if between_digits => Err(format!("{} isn't a finite number", word)),
+        _ => Err(format!("bad value '{}'", word)),
+    }
+}
+
+// Where a path leads, with the index of the last table wherever it goes
+// through an array of tables, as current_table goes: "a.b" or "a[2].b".
+fn where_is(root: &Table, path: &[String]) -> String {
+    let mut table = Some(root);
+    let mut place = String::new();
+    for key in path {
+        if !place.is_empty() {
+            place.push('.');
+        }
+        place.push_str(key);
+        table = match table.and_then(|t| t.get(key)) {
+            Some(Value::Table(t)) => Some(t),
+            Some(Value::Array(a)) => match a.last() {
+                Some(Value::Table(t)) => {
+                    place.push_str(&format!("[{}]", a.len() - 1));
+                    Some(t)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+    }
+    place
+}
+
+// Create the table for a [header] or [[header]].
+fn open_table(root: &mut Table, path: &[String], array: bool) -> Result<(), String> {
+    let (last, parents) = path.split_last().unwrap();
+    let parent = current_table(root, parents)?;
+    match parent.get_mut(last) {
+        None if array => {
+            parent.insert(last.clone(), Value::Array(vec![Value::Table(Table::new())]));
+        }
+        None => {
+            parent.insert(last.clone(), Value::Table(Table::new()));
+        }
+        Some(Value::Array(tables)) if array => tables.push(Value::Table(Table::new())),
+        // A table made implicitly by [a.b] can still get its own [a] header.
+        Some(Value::Table(_)) if !array => {}
+        Some(_) => return Err(format!("{} is defined twice", path.join("."))),
+    }
+    Ok(())
+}
+
+// The table at `path`, creating any that are missing.  A path through an
+// array of tables goes to its last table.
+fn current_table<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut table = root;
+    for key in path {
+        let value = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match value {
+            Value::Table(t) => t,
+            Value::Array(a) => match a.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err(format!("{} isn't an array of tables", key)),
+            },
+            _ => return Err(format!("{} isn't a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_numbers, get_tables, parse, Value};
+
+    #[test]
+    fn outside_the_subset() {
+        for (text, error) in [
+            ("a = inf", "line 1: inf isn't a finite number"),
+            ("a = +inf", "line 1: +inf isn't a finite number"),
+            ("a = nan", "line 1: nan isn't a finite number"),
+            ("a = 1e400", "line 1: 1e400 isn't a finite number"),
+            ("a = .5", "line 1: bad value '.5'"),
+            ("a = 1_", "line 1: bad value '1_'"),
+            ("a = 0x10", "line 1: bad value '0x10'"),
+            ("a = 2020-01-01", "line 1: bad value '2020-01-01'"),
+            ("a = 'literal'", "line 1: expected a value"),
+            ("a.b = 1", "line 1: expected '=', found '.'"),
+            ("[a]\n[a]", "line 2: a is defined twice"),
+            ("[a.b]\n[a]\n[a.b]", "line 3: a.b is defined twice"),
+            (
+                "a = { b = 1 }\n[a]",
+                "line 2: a was given as a value, so a header can't add to it",
+            ),
+            (
+                "[x]\na = { b = 1 }\n[x.a.c]",
+                "line 3: x.a was given as a value, so a header can't add to it",
+            ),
+            (
+                "a = [{ b = 1 }]\n[[a]]",
+                "line 2: a was given as a value, so a header can't add to it",
+            ),
+            (
+                "a = [1]\n[a.b]",
+                "line 2: a was given as a value, so a header can't add to it",
+            ),
+        ]
+        .iter()
+        {
+            assert_eq!(parse(text).unwrap_err(), *error, "{}", text);
+        }
+
+        // Each table of an array of tables can have its own subtable.
+        let table = parse("[[a]]\n[a.b]\nc = 1\n[[a]]\n[a.b]\nc = 2\n").unwrap();
+        assert_eq!(get_tables(&table, "a").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn parse_toml() {
+        let text = r#"
+            # A comment.
+            name = "4% \"rule\""  # Another.
+            start_portfolio = 1_000_000
+            verbose = false
+
+            [allocation]
+            fractions = [
+                0.4, 0.5,
+                0.6,  # Trailing comma next.
+            ]
+            mix = { gold = 0.2, stocks = 0.8 }
+
+            [[expenses]]
+            amount = 40e3
+
+            [[expenses]]
+            amount = -1.5
+            "#;
+        let table = parse(text).unwrap();
+        assert_eq!(table["name"], Value::String("4% \"rule\"".to_string()));
+        assert_eq!(table["start_portfolio"], Value::Number(1e6));
+        assert_eq!(table["verbose"], Value::Bool(false));
+        let Value::Table(allocation) = &table["allocation"] else {
+            panic!();
+        };
+        assert_eq!(
+            get_numbers(allocation, "fractions").unwrap(),
+            Some(vec![0.4, 0.5, 0.6])
+        );
+        let Value::Table(mix) = &allocation["mix"] else {
+            panic!();
+        };
+        assert_eq!(mix["gold"], Value::Number(0.2));
+        let expenses = get_tables(&table, "expenses").unwrap();
+        assert_eq!(expenses.len(), 2);
+        assert_eq!(expenses[1]["amount"], Value::Number(-1.5));
+
+        assert!(get_numbers(&table, "name").is_err());
+        assert_eq!(
+            parse("a = 1\na = 2\n").unwrap_err(),
+            "line 2: a is defined twice"
+        );
+        assert_eq!(
+            parse("\n\nb = [1, 2").unwrap_err(),
+            "line 3: expected ',' or ']' in array"
+        );
+        assert!(parse("c = \"open").is_err());
+        assert!(parse("d = 1 2").is_err());
+        assert_eq!(
+            parse("e = 1\n[e.f]").unwrap_err(),
+            "line 2: e isn't a table"
+        );
+    }
+}
@@ -0,0 +1,656 @@
+use crate::time_series;
+use std::collections::BTreeMap;
+
+#[cfg(test)]
+mod tests {
+    use super::{Dataset, CAPE};
+    use crate::time_series;
+    use crate::Backtest;
+
+    #[test]
+    fn shiller_csv() {
+        let text = "Date,Real Total Return Price,Real Total Bond Returns,CPI
+            1929.09,100,50,10
+            1929.1,90,51,10.1
+            1929.11,81,51,10.1";
+        let dataset = Dataset::from_shiller_csv("test", text).unwrap();
+        assert_eq!(dataset.len(), 2);
+        assert_eq!(dataset.date(1), (1929, 10));
+        assert!((dataset.stocks[1] + 10.).abs() < 1e-9);
+        assert!((dataset.bonds[0] - 2.).abs() < 1e-9);
+        assert!((dataset.inflation[0] - 1.).abs() < 1e-9);
+
+        assert!(Dataset::from_shiller_csv("test", "1929.09,1,1,1\n1929.11,1,1,1").is_err());
+    }
+
+    #[test]
+    fn monthly_matches_annual() {
+        const YEARS: usize = 10;
+        let annual = Dataset::us_annual();
+        let monthly = annual.spread_over_months();
+
+        // Stock fractions of 0 and 1 aren't affected by monthly rebalancing.
+        let fractions: Vec<f64> = (0..YEARS).map(|i| (i % 2) as f64).collect();
+        let expenses = vec![40.; YEARS];
+        let mut monthly_fractions = Vec::new();
+        let mut monthly_expenses = Vec::new();
+        for i in 0..YEARS {
+            monthly_fractions.extend(std::iter::repeat_n(fractions[i], 12));
+            monthly_expenses.push(expenses[i]);
+            monthly_expenses.extend(std::iter::repeat_n(0., 11));
+        }
+
+        let by_year = Backtest::from_dataset(1000., expenses, vec![0.; YEARS], &annual);
+        let by_month =
+            Backtest::from_dataset(1000., monthly_expenses, vec![0.; YEARS * 12], &monthly);
+        for start in [0, 58, 95] {
+            let a = by_year.single_run(&fractions, start, false);
+            let m = by_month.single_run(&monthly_fractions, start * 12, false);
+            assert!((a - m).abs() < 1e-6);
+        }
+        assert_eq!(
+            by_month.worst_year(&monthly_fractions).0,
+            by_year.worst_year(&fractions).0
+        );
+    }
+
+    #[test]
+    fn mix_assets() {
+        let mut us = Dataset::us_annual();
+        // Not real gold returns, just a shorter history.
+        let gold: String = (1972..=2020)
+            .map(|y| format!("{},{}\n", y, y % 7))
+            .collect();
+        us.load_asset("gold", &gold).unwrap();
+
+        let butterfly = [
+            ("total_stock_market", 0.4),
+            ("long_term_treasuries", 0.2),
+            ("short_term_treasuries", 0.2),
+            ("gold", 0.2),
+        ];
+        let mixed = us
+            .mix(&butterfly, &[("short_term_treasuries", 1.)])
+            .unwrap();
+        assert_eq!(mixed.first_year, 1972);
+        assert_eq!(mixed.len(), 49);
+        assert_eq!(mixed.series.len(), 0);
+        let expected = 0.4 * time_series::TOTAL_STOCK_MARKET[1980 - 1871]
+            + 0.2 * time_series::LONG_TERM_TREASURIES[1980 - 1871]
+            + 0.2 * time_series::SHORT_TERM_TREASURIES[1980 - 1871]
+            + 0.2 * (1980 % 7) as f64;
+        assert!((mixed.stocks[1980 - 1972] - expected).abs() < 1e-9);
+        assert!(us
+            .mix(&[("gold", 0.5)], &[("short_term_treasuries", 1.)])
+            .is_err());
+        assert!(us
+            .mix(&[("silver", 1.)], &[("short_term_treasuries", 1.)])
+            .is_err());
+
+        // A 60/40 mix at 100% "stocks" is the same as 60% stocks.
+        let sixty_forty = us
+            .mix(&[("stocks", 0.6), ("bonds", 0.4)], &[("bonds", 1.)])
+            .unwrap();
+        let mixed = Backtest::from_dataset(1000., vec![40.; 30], vec![0.; 30], &sixty_forty);
+        let plain = Backtest::from_dataset(1000., vec![40.; 30], vec![0.; 30], &us);
+        assert!(
+            (mixed.single_run(&[1.; 30], 60, false) - plain.single_run(&[0.6; 30], 60, false))
+                .abs()
+                < 1e-9
+        );
+    }
+
+    #[test]
+    fn cape_is_loaded_not_bundled() {
+        let us = Dataset::us_annual();
+        assert!(us.series(CAPE).is_none());
+        assert_eq!(
+            us.bucket_by(CAPE, &[15., 25.]).err().unwrap(),
+            "no series named cape; load it with load_series"
+        );
+    }
+}
+
+// Name of the Shiller CAPE (cyclically adjusted P/E) series.  It isn't
+// bundled, since time_series has none: load Shiller's with load_series, or
+// as the cape column of from_shiller_csv.
+pub const CAPE: &str = "cape";
+
+// A market history the backtester can step through.  Each period is either a
+// year or a month.  Stock and bond returns are real (inflation adjusted) and,
+// like everything in time_series, are in percent.
+//
+// `assets` holds real returns, in percent, of other asset classes such as
+// gold or REITs.  `series` holds any other per-period data, such as CAPE or
+// bond yields, measured at the start of each period.  Periods without data
+// are NaN in both.
+#[derive(Clone)]
+pub struct Dataset {
+    pub name: String,
+    pub source: String, // Where the numbers came from, for reports.
+    pub first_year: usize,
+    pub first_month: usize, // 1 = January.  Always 1 for annual data.
+    pub periods_per_year: usize,
+    pub stocks: Vec<f64>,
+    pub bonds: Vec<f64>,
+    pub inflation: Vec<f64>,
+    pub assets: BTreeMap<String, Vec<f64>>,
+    pub series: BTreeMap<String, Vec<f64>>,
+}
+
+impl Dataset {
+    // The annual US series from Simba's spreadsheet, 1871 - 2020.  All of
+    // time_series' return series are available as assets.  Cash, T-bills,
+    // TIPS, gold, REITs and small cap value aren't bundled, so load them with
+    // load_asset, or `assets = { gold = "gold.csv" }` in a scenario, and CAPE
+    // likewise with load_series.  Short term treasuries are the nearest thing
+    // to cash here, but they're notes with a few years' duration, not cash.
+    pub fn us_annual() -> Self {
+        let assets = [
+            ("total_stock_market", &time_series::TOTAL_STOCK_MARKET),
+            ("large_cap_blend", &time_series::LARGE_CAP_BLEND),
+            ("total_bond_market", &time_series::TOTAL_BOND_MARKET),
+            ("short_term_treasuries", &time_series::SHORT_TERM_TREASURIES),
+            (
+                "intermediate_term_treasuries",
+                &time_series::INTERMEDIATE_TERM_TREASURIES,
+            ),
+            ("long_term_treasuries", &time_series::LONG_TERM_TREASURIES),
+            ("short_term_bonds", &time_series::SHORT_TERM_BONDS),
+            (
+                "intermediate_term_bonds",
+                &time_series::INTERMEDIATE_TERM_BONDS,
+            ),
+        ];
+        Dataset {
+            name: "us".to_string(),
+            source: "Simba's backtesting spreadsheet (Bogleheads), annual real returns".to_string(),
+            first_year: time_series::FIRST_YEAR,
+            first_month: 1,
+            periods_per_year: 1,
+            stocks: time_series::TOTAL_STOCK_MARKET.to_vec(),
+            bonds: time_series::TOTAL_BOND_MARKET.to_vec(),
+            inflation: time_series::INFLATION.to_vec(),
+            assets: assets
+                .iter()
+                .map(|(name, returns)| (name.to_string(), returns.to_vec()))
+                .collect(),
+            series: BTreeMap::new(),
+        }
+    }
+
+    // Parse monthly data in the layout of Shiller's ie_data spreadsheet,
+    // exported as CSV with the columns:
+    //
+    //     date,stocks,bonds,cpi[,cape]
+    //
+    // where date is Shiller's "1871.01" format, stocks is the "Real Total
+    // Return Price" column, bonds is "Real Total Bond Returns", and cpi is the
+    // Consumer Price Index.  These are index levels, so each row's return is
+    // measured from the previous row.  The optional cape column is Shiller's
+    // CAPE, which is blank or "NA" for the first ten years.  Blank lines and
+    // lines starting with '#' are ignored, as is a header row.
+    //
+    // No monthly history is bundled, so this is the way to get one: download
+    // ie_data.xls from Shiller's web site and export those columns.
+    pub fn from_shiller_csv(name: &str, text: &str) -> Result<Self, String> {
+        let rows = parse_rows(text)?;
+        if rows.len() < 2 {
+            return Err("need at least two monthly rows".to_string());
+        }
+
+        let mut dates = Vec::new();
+        let mut levels: Vec<[f64; 3]> = Vec::new();
+        let mut cape = Vec::new();
+        let has_cape = rows[0].1.len() == 5;
+        for (line, row) in rows {
+            if row.len() != 4 + has_cape as usize {
+                return Err(format!(
+                    "line {}: expected {} columns, got {}",
+                    line,
+                    4 + has_cape as usize,
+                    row.len()
+                ));
+            }
+            if has_cape {
+                cape.push(parse_optional(&row[4]).map_err(|e| format!("line {}: {}", line, e))?);
+            }
+            dates.push(parse_date(&row[0]).map_err(|e| format!("line {}: {}", line, e))?);
+            let mut level = [0.; 3];
+            for (j, field) in row[1..].iter().enumerate() {
+                level[j] = field
+                    .parse()
+                    .map_err(|_| format!("line {}: bad number '{}'", line, field))?;
+                if level[j] <= 0. {
+                    return Err(format!("line {}: levels must be positive", line));
+                }
+            }
+            levels.push(level);
+        }
+
+        for i in 1..dates.len() {
+            let (year, month) = dates[i - 1];
+            let expected = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+            if dates[i] != expected {
+                return Err(format!(
+                    "dates must be consecutive months: {}.{:02} follows {}.{:02}",
+                    dates[i].0, dates[i].1, year, month
+                ));
+            }
+        }
+
+        let change = |i: usize, j: usize| (levels[i][j] / levels[i - 1][j] - 1.) * 100.;
+        let mut stocks = Vec::new();
+        let mut bonds = Vec::new();
+        let mut inflation = Vec::new();
+        for i in 1..levels.len() {
+            stocks.push(change(i, 0));
+            bonds.push(change(i, 1));
+            inflation.push(change(i, 2));
+        }
+
+        let mut series = BTreeMap::new();
+        if has_cape {
+            cape.pop();
+            series.insert(CAPE.to_string(), cape);
+        }
+
+        // The return over a month is attributed to the month it starts in.
+        Ok(Dataset {
+            name: name.to_string(),
+            source: "Shiller ie_data CSV, monthly".to_string(),
+            first_year: dates[0].0,
+            first_month: dates[0].1,
+            periods_per_year: 12,
+            stocks,
+            bonds,
+            inflation,
+            assets: BTreeMap::new(),
+            series,
+        })
+    }
+
+    // Parse annual data in the same form as time_series, e.g. for another
+    // country or a global portfolio, with the columns:
+    //
+    //     year,stocks,bonds,inflation
+    //
+    // Stock and bond returns are real, and all three are in percent.  Years
+    // must be consecutive.
+    pub fn from_annual_csv(name: &str, text: &str) -> Result<Self, String> {
+        let mut years = Vec::new();
+        let mut stocks = Vec::new();
+        let mut bonds = Vec::new();
+        let mut inflation = Vec::new();
+        for (line, row) in parse_rows(text)? {
+            if row.len() != 4 {
+                return Err(format!(
+                    "line {}: expected 4 columns, got {}",
+                    line,
+                    row.len()
+                ));
+            }
+            let year: usize = row[0]
+                .parse()
+                .map_err(|_| format!("line {}: bad year '{}'", line, row[0]))?;
+            if years.last().is_some_and(|last| year != last + 1) {
+                return Err(format!("line {}: years must be consecutive", line));
+            }
+            years.push(year);
+            let mut values = [0.; 3];
+            for (j, field) in row[1..].iter().enumerate() {
+                values[j] = field
+                    .parse()
+                    .map_err(|_| format!("line {}: bad number '{}'", line, field))?;
+            }
+            stocks.push(values[0]);
+            bonds.push(values[1]);
+            inflation.push(values[2]);
+        }
+
+        Ok(Dataset {
+            name: name.to_string(),
+            source: "annual CSV".to_string(),
+            first_year: years[0],
+            first_month: 1,
+            periods_per_year: 1,
+            stocks,
+            bonds,
+            inflation,
+            assets: BTreeMap::new(),
+            series: BTreeMap::new(),
+        })
+    }
+
+    // Spread each annual return evenly (geometrically) over 12 months.  This
+    // isn't monthly history: every month of a year has the same return, so
+    // crashes within a year, like 1987 or March 2020, don't show, so it's
+    // only for testing the monthly engine against the annual one.  For
+    // monthly data, see from_shiller_csv.
+    #[cfg(test)]
+    pub(crate) fn spread_over_months(&self) -> Self {
+        assert_eq!(self.periods_per_year, 1);
+        let spread = |annual: &[f64]| -> Vec<f64> {
+            let mut monthly = Vec::new();
+            for r in annual {
+                let m = ((1. + r / 100.).powf(1. / 12.) - 1.) * 100.;
+                monthly.extend(std::iter::repeat_n(m, 12));
+            }
+            monthly
+        };
+
+        Dataset {
+            name: format!("{} (annual spread over months)", self.name),
+            source: format!(
+                "{}, each year's returns spread evenly over its months, not monthly data",
+                self.source
+            ),
+            first_year: self.first_year,
+            first_month: 1,
+            periods_per_year: 12,
+            stocks: spread(&self.stocks),
+            bonds: spread(&self.bonds),
+            inflation: spread(&self.inflation),
+            assets: self
+                .assets
+                .iter()
+                .map(|(name, returns)| (name.clone(), spread(returns)))
+                .collect(),
+            series: self
+                .series
+                .iter()
+                .map(|(name, values)| {
+                    let monthly = values.iter().flat_map(|v| std::iter::repeat_n(*v, 12));
+                    (name.clone(), monthly.collect())
+                })
+                .collect(),
+        }
+    }
+
+    // Add a per-period series from CSV rows of `date,value`.  Dates are years
+    // ("1881") or Shiller style months ("1881.01"); an annual dataset only
+    // takes January values.  Periods outside the file are NaN, as are blank
+    // or "NA" values.
+    pub fn load_series(&mut self, name: &str, text: &str) -> Result<(), String> {
+        let values = self.parse_aligned(text)?;
+        self.series.insert(name.to_string(), values);
+        Ok(())
+    }
+
+    // Add an asset class's real returns, in percent, from CSV rows of
+    // `date,return` as for load_series.  Use this for gold, REITs, TIPS, small
+    // cap value or anything else time_series doesn't have.
+    pub fn load_asset(&mut self, name: &str, text: &str) -> Result<(), String> {
+        let returns = self.parse_aligned(text)?;
+        self.assets.insert(name.to_string(), returns);
+        Ok(())
+    }
+
+    fn parse_aligned(&self, text: &str) -> Result<Vec<f64>, String> {
+        let mut values = vec![f64::NAN; self.len()];
+        for (line, row) in parse_rows(text)? {
+            if row.len() != 2 {
+                return Err(format!(
+                    "line {}: expected 2 columns, got {}",
+                    line,
+                    row.len()
+                ));
+            }
+            let (year, month) = parse_date(&row[0]).map_err(|e| format!("line {}: {}", line, e))?;
+            if self.periods_per_year == 1 && month != 1 {
+                continue;
+            }
+            let months =
+                (year * 12 + month) as isize - (self.first_year * 12 + self.first_month) as isize;
+            let offset = months * self.periods_per_year as isize / 12;
+            if (0..values.len() as isize).contains(&offset) {
+                values[offset as usize] =
+                    parse_optional(&row[1]).map_err(|e| format!("line {}: {}", line, e))?;
+            }
+        }
+        Ok(values)
+    }
+
+    // Real returns of a named asset class.  "stocks" and "bonds" are the
+    // dataset's own.
+    pub fn asset(&self, name: &str) -> Option<&[f64]> {
+        match name {
+            "stocks" => Some(&self.stocks),
+            "bonds" => Some(&self.bonds),
+            _ => self.assets.get(name).map(|v| v.as_slice()),
+        }
+    }
+
+    // A dataset whose "stocks" are a portfolio of asset classes with fixed
+    // weights, rebalanced every period, and likewise for "bonds".  Stock
+    // fractions then move money between the two, so `risky` could be a
+    // Golden Butterfly and `safe` short term treasuries.
+    //
+    // Asset classes with shorter histories are NaN outside them, so the
+    // result is cut down to the longest stretch where every asset in the mix
+    // has data.
+    pub fn mix(&self, risky: &[(&str, f64)], safe: &[(&str, f64)]) -> Result<Dataset, String> {
+        let weighted = |weights: &[(&str, f64)]| -> Result<Vec<f64>, String> {
+            let total: f64 = weights.iter().map(|(_, w)| w).sum();
+            if (total - 1.).abs() > 1e-9 {
+                return Err(format!("weights add up to {}, not 1", total));
+            }
+            let mut returns = vec![0.; self.len()];
+            for (name, weight) in weights {
+                let asset = self
+                    .asset(name)
+                    .ok_or_else(|| format!("no asset class named {}", name))?;
+                for (r, a) in returns.iter_mut().zip(asset) {
+                    *r += weight * a;
+                }
+            }
+            Ok(returns)
+        };
+        let stocks = weighted(risky)?;
+        let bonds = weighted(safe)?;
+
+        // Find the longest run of periods with data for everything.
+        let mut best = 0..0;
+        let mut start = 0;
+        for i in 0..=self.len() {
+            if i == self.len() || stocks[i].is_nan() || bonds[i].is_nan() {
+                if i - start > best.len() {
+                    best = start..i;
+                }
+                start = i + 1;
+            }
+        }
+        if best.is_empty() {
+            return Err("no periods with data for every asset".to_string());
+        }
+
+        let trim = |values: &[f64]| values[best.clone()].to_vec();
+        let (first_year, first_month) = self.date(best.start);
+        let describe = |weights: &[(&str, f64)]| {
+            let parts: Vec<String> = weights
+                .iter()
+                .map(|(name, weight)| format!("{}% {}", weight * 100., name))
+                .collect();
+            parts.join(" + ")
+        };
+        Ok(Dataset {
+            name: self.name.clone(),
+            source: format!(
+                "{}; stocks = {}, bonds = {}",
+                self.source,
+                describe(risky),
+                describe(safe)
+            ),
+            first_year,
+            first_month,
+            periods_per_year: self.periods_per_year,
+            stocks: trim(&stocks),
+            bonds: trim(&bonds),
+            inflation: trim(&self.inflation),
+            assets: self
+                .assets
+                .iter()
+                .map(|(k, v)| (k.clone(), trim(v)))
+                .collect(),
+            series: self
+                .series
+                .iter()
+                .map(|(k, v)| (k.clone(), trim(v)))
+                .collect(),
+        })
+    }
+
+    // A named per-period series.  "stocks", "bonds" and "inflation" are the
+    // returns themselves.
+    pub fn series(&self, name: &str) -> Option<&[f64]> {
+        match name {
+            "stocks" => Some(&self.stocks),
+            "bonds" => Some(&self.bonds),
+            "inflation" => Some(&self.inflation),
+            _ => self.series.get(name).map(|v| v.as_slice()),
+        }
+    }
+
+    // A key for Backtest::group_windows: which bucket the named series falls
+    // in at the start of each window.  Edges [15, 25] give the buckets
+    // "< 15", "15 - 25" and ">= 25".  Windows starting without data are left
+    // out.  An error if the dataset has no such series, as us_annual has no
+    // CAPE until it's loaded.
+    pub fn bucket_by<'a>(
+        &'a self,
+        name: &str,
+        edges: &'a [f64],
+    ) -> Result<impl Fn(usize) -> Option<String> + 'a, String> {
+        let values = self
+            .series(name)
+            .ok_or_else(|| format!("no series named {}; load it with load_series", name))?;
+        assert!(edges.windows(2).all(|w| w[0] < w[1]));
+        Ok(move |offset: usize| {
+            let value = values[offset];
+            if value.is_nan() {
+                return None;
+            }
+            let above = edges.iter().filter(|e| value >= **e).count();
+            Some(match above {
+                0 => format!("< {}", edges[0]),
+                n if n == edges.len() => format!(">= {}", edges[n - 1]),
+                n => format!("{} - {}", edges[n - 1], edges[n]),
+            })
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.stocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stocks.is_empty()
+    }
+
+    // Calendar (year, month) of the period at the given offset.
+    pub fn date(&self, offset: usize) -> (usize, usize) {
+        let months = self.first_month - 1 + offset * 12 / self.periods_per_year;
+        (self.first_year + months / 12, months % 12 + 1)
+    }
+}
+
+// Market histories by name, e.g. "us", "japan", "uk", "global".  Each has its
+// own first year and length.
+#[derive(Clone)]
+pub struct Datasets {
+    datasets: BTreeMap<String, Dataset>,
+}
+
+impl Datasets {
+    // Starts with the bundled US history, as "us".
+    pub fn new() -> Self {
+        let mut datasets = Datasets {
+            datasets: BTreeMap::new(),
+        };
+        datasets.add(Dataset::us_annual());
+        datasets
+    }
+
+    pub fn add(&mut self, dataset: Dataset) {
+        self.datasets.insert(dataset.name.clone(), dataset);
+    }
+
+    // Add an annual history from CSV; see Dataset::from_annual_csv.
+    pub fn load_annual_csv(&mut self, name: &str, text: &str) -> Result<(), String> {
+        self.add(Dataset::from_annual_csv(name, text)?);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Dataset> {
+        self.datasets.get(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.datasets.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Dataset> {
+        self.datasets.values()
+    }
+}
+
+impl Default for Datasets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Split CSV text into rows of trimmed fields, keeping the 1-based line number
+// for error messages.  A first row that doesn't start with a number is taken
+// to be a header and skipped.
+pub(crate) fn parse_rows(text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut rows = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<String> = line.split(',').map(|f| f.trim().to_string()).collect();
+        let is_header = rows.is_empty()
+            && fields[0]
+                .chars()
+                .next()
+                .is_some_and(|c| !c.is_ascii_digit() && c != '-' && c != '.');
+        if !is_header {
+            rows.push((i + 1, fields));
+        }
+    }
+    if rows.is_empty() {
+        return Err("no data rows".to_string());
+    }
+    Ok(rows)
+}
+
+fn parse_optional(field: &str) -> Result<f64, String> {
+    if field.is_empty() || field == "NA" {
+        return Ok(f64::NAN);
+    }
+    field.parse().map_err(|_| format!("bad number '{}'", field))
+}
+
+// Shiller writes October 1929 as "1929.10" and January as "1929.01", so the
+// fraction is a month number, not a decimal fraction of the year.  A bare
+// year is January.
+fn parse_date(field: &str) -> Result<(usize, usize), String> {
+    let bad = || format!("bad date '{}', expected YYYY.MM", field);
+    let (year, month) = field.split_once('.').unwrap_or((field, "01"));
+    let year: usize = year.parse().map_err(|_| bad())?;
+    let month: usize = match month.len() {
+        1 if month == "1" => 10, // Spreadsheet exports drop October's trailing zero.
+        2 => month.parse().map_err(|_| bad())?,
+        _ => return Err(bad()),
+    };
+    if !(1..=12).contains(&month) {
+        return Err(bad());
+    }
+    Ok((year, month))
+}
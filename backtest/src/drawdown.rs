@@ -0,0 +1,274 @@
+use crate::{grid_search, Backtest, Range};
+use std::fmt;
+
+// Path risk: two windows can end with the same balance, one smoothly and one
+// after dropping to half its starting value in year 3.  All measured on the
+// real portfolio after expenses, so spending counts as a drawdown too.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Drawdown {
+    // Largest fall from a previous peak, as a fraction of the peak.
+    pub max_drawdown: f64,
+    // Time spent below the starting balance.
+    pub years_underwater: f64,
+    // Root mean square of the percent drawdown over every period.
+    pub ulcer_index: f64,
+    pub min_balance: f64,
+}
+
+impl Drawdown {
+    // From the starting balance and the balance at the end of each period.
+    pub fn new(start_portfolio: f64, path: &[f64], periods_per_year: usize) -> Self {
+        let mut peak = start_portfolio;
+        let mut max_drawdown: f64 = 0.;
+        let mut underwater = 0;
+        let mut squares = 0.;
+        let mut min_balance = start_portfolio;
+        for &value in path {
+            peak = peak.max(value);
+            let drawdown = (peak - value) / peak;
+            max_drawdown = max_drawdown.max(drawdown);
+            squares += (drawdown * 100.).powi(2);
+            underwater += (value < start_portfolio) as usize;
+            min_balance = min_balance.min(value);
+        }
+        Drawdown {
+            max_drawdown,
+            years_underwater: underwater as f64 / periods_per_year as f64,
+            ulcer_index: (squares / path.len() as f64).sqrt(),
+            min_balance,
+        }
+    }
+}
+
+// Which number of a Drawdown to rank windows by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    MaxDrawdown,
+    YearsUnderwater,
+    UlcerIndex,
+    MinBalance,
+}
+
+impl Metric {
+    pub fn value(&self, drawdown: &Drawdown) -> f64 {
+        match self {
+            Metric::MaxDrawdown => drawdown.max_drawdown,
+            Metric::YearsUnderwater => drawdown.years_underwater,
+            Metric::UlcerIndex => drawdown.ulcer_index,
+            Metric::MinBalance => drawdown.min_balance,
+        }
+    }
+
+    // A value turned around, if need be, so that smaller is worse, the way
+    // worst_year ranks ending values.
+    fn score(&self, value: f64) -> f64 {
+        match self {
+            Metric::MinBalance => value,
+            _ => -value,
+        }
+    }
+}
+
+// The worst window by each metric, plus the average maximum drawdown.
+#[derive(Clone, Debug)]
+pub struct DrawdownStats {
+    pub windows: usize,
+    pub mean_max_drawdown: f64,
+    pub max_drawdown: (usize, f64), // (Start year, value) of the worst window.
+    pub years_underwater: (usize, f64),
+    pub ulcer_index: (usize, f64),
+    pub min_balance: (usize, f64),
+}
+
+impl fmt::Display for DrawdownStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} windows, mean max drawdown {:.1}%, worst max drawdown {:.1}% starting {}, \
+             {:.1} years underwater starting {}, Ulcer Index {:.1} starting {}, \
+             lowest balance ${:.3} M starting {}",
+            self.windows,
+            self.mean_max_drawdown * 100.,
+            self.max_drawdown.1 * 100.,
+            self.max_drawdown.0,
+            self.years_underwater.1,
+            self.years_underwater.0,
+            self.ulcer_index.1,
+            self.ulcer_index.0,
+            self.min_balance.1 / 1e6,
+            self.min_balance.0
+        )
+    }
+}
+
+impl Backtest {
+    pub fn drawdown(&self, stock_fractions: &[f64], year_offset: usize) -> Drawdown {
+        Drawdown::new(
+            self.start_portfolio,
+            &self.portfolio_path(stock_fractions, year_offset),
+            self.periods_per_year,
+        )
+    }
+
+    // The drawdown of every historical window, with its start year.
+    pub fn drawdowns(&self, stock_fractions: &[f64]) -> Vec<(usize, Drawdown)> {
+        self.window_offsets(self.real_expenses.len())
+            .into_iter()
+            .map(|year_offset| {
+                (
+                    self.year_of(year_offset),
+                    self.drawdown(stock_fractions, year_offset),
+                )
+            })
+            .collect()
+    }
+
+    pub fn drawdown_stats(&self, stock_fractions: &[f64]) -> DrawdownStats {
+        let drawdowns = self.drawdowns(stock_fractions);
+        let worst = |metric: Metric| {
+            let (year, drawdown) = drawdowns
+                .iter()
+                .min_by(|a, b| {
+                    metric
+                        .score(metric.value(&a.1))
+                        .total_cmp(&metric.score(metric.value(&b.1)))
+                })
+                .unwrap();
+            (*year, metric.value(drawdown))
+        };
+        DrawdownStats {
+            windows: drawdowns.len(),
+            mean_max_drawdown: drawdowns.iter().map(|(_, d)| d.max_drawdown).sum::<f64>()
+                / drawdowns.len() as f64,
+            max_drawdown: worst(Metric::MaxDrawdown),
+            years_underwater: worst(Metric::YearsUnderwater),
+            ulcer_index: worst(Metric::UlcerIndex),
+            min_balance: worst(Metric::MinBalance),
+        }
+    }
+
+    // Like worst_year, ranking windows by a drawdown metric instead of the
+    // ending value.  Values are the metric's own, e.g. the largest drawdowns.
+    // With a single window the second worst is (usize::MAX, NaN).
+    pub fn worst_year_drawdown(
+        &self,
+        metric: Metric,
+        stock_fractions: &[f64],
+    ) -> (usize, f64, usize, f64) {
+        let (worst_year, _, second_worst_year, _) = self.worst_offsets(|year_offset| {
+            Some(metric.score(metric.value(&self.drawdown(stock_fractions, year_offset))))
+        });
+        let value = |offset| {
+            if offset == usize::MAX {
+                return f64::NAN;
+            }
+            metric.value(&self.drawdown(stock_fractions, offset))
+        };
+        (
+            self.year_of(worst_year),
+            value(worst_year),
+            self.year_of(second_worst_year),
+            value(second_worst_year),
+        )
+    }
+
+    // Like best_fractions, but finds the glide path whose worst window by
+    // `metric` is least bad.  Returns the parameters, the metric's value and
+    // the year it happened.
+    pub fn best_fractions_drawdown(
+        &self,
+        ranges: &[Range],
+        get_fractions: fn(&[f64], usize) -> Vec<f64>,
+        length: usize,
+        metric: Metric,
+    ) -> (Vec<f64>, f64, usize) {
+        let (best_values, _, (best_year, value)) = grid_search(ranges, |values| {
+            let stock_fractions = get_fractions(values, length);
+            let (year, value, _, _) = self.worst_year_drawdown(metric, &stock_fractions);
+            (metric.score(value), (year, value))
+        });
+        (best_values, value, best_year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Drawdown, Metric};
+    use crate::time_series;
+    use crate::windows::StartYears;
+    use crate::{Backtest, Range};
+
+    #[test]
+    fn drawdown_of_path() {
+        let drawdown = Drawdown::new(100., &[120., 90., 60., 110., 130.], 1);
+        assert_eq!(drawdown.max_drawdown, 0.5);
+        assert_eq!(drawdown.years_underwater, 2.);
+        assert_eq!(drawdown.min_balance, 60.);
+        // Drawdowns of 0, 25, 50, 8.33 and 0 percent.
+        let squares: f64 = 625. + 2500. + (100. / 12.0f64).powi(2);
+        assert!((drawdown.ulcer_index - (squares / 5.).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn worst_drawdowns() {
+        let backtest = Backtest::new(
+            1000.,
+            vec![40.; 30],
+            vec![0.; 30],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        );
+        let fractions = [0.8; 30];
+        let path = backtest.portfolio_path(&fractions, 10);
+        assert_eq!(path.len(), 30);
+        assert_eq!(path[29], backtest.single_run(&fractions, 10, false));
+
+        let stats = backtest.drawdown_stats(&fractions);
+        let (year, value, second_year, second_value) =
+            backtest.worst_year_drawdown(Metric::MaxDrawdown, &fractions);
+        assert_eq!(stats.max_drawdown, (year, value));
+        assert!(second_value <= value && second_year != year);
+        // Balances are after expenses, so the lowest balance ever is no
+        // higher than the worst ending value.
+        assert!(stats.min_balance.1 <= backtest.worst_year(&fractions).1);
+
+        let get_fractions = |values: &[f64], length: usize| vec![values[0]; length];
+        let (values, best, _) = backtest.best_fractions_drawdown(
+            &[Range::new(0.2, 1.0, 0.4)],
+            get_fractions,
+            30,
+            Metric::UlcerIndex,
+        );
+        for f in [0.2, 0.6, 1.0] {
+            let (_, ulcer, _, _) = backtest.worst_year_drawdown(Metric::UlcerIndex, &vec![f; 30]);
+            assert!(best <= ulcer);
+        }
+        assert_eq!(
+            best,
+            backtest
+                .worst_year_drawdown(Metric::UlcerIndex, &vec![values[0]; 30])
+                .1
+        );
+
+        // A single window has no second worst.
+        let one = Backtest::new(
+            1000.,
+            vec![40.; 30],
+            vec![0.; 30],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+        .with_start_years(StartYears::List(vec![1966]));
+        let (year, value, second_year, second_value) =
+            one.worst_year_drawdown(Metric::MaxDrawdown, &fractions);
+        assert_eq!((year, second_year), (1966, usize::MAX));
+        assert!(value > 0. && second_value.is_nan());
+        let (_, _, year) = one.best_fractions_drawdown(
+            &[Range::new(0.2, 1.0, 0.4)],
+            get_fractions,
+            30,
+            Metric::MaxDrawdown,
+        );
+        assert_eq!(year, 1966);
+    }
+}
@@ -0,0 +1,136 @@
+// One-off cash flows: a new roof in year 7, a car every 10 years, an
+// inheritance at 70.  They're paid on top of the expenses, the same way in
+// every historical window.  With monthly data an event is paid in full in the
+// first month of its year.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dollars {
+    Real,    // Inflation adjusted, like real_expenses.
+    Nominal, // Start year dollars, like nominal_expenses.
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub label: String,
+    pub year: usize, // Years after the start of retirement.
+    pub amount: f64, // Positive for an expense, negative for a windfall.
+    pub dollars: Dollars,
+    pub every: Option<usize>, // Recur every this many years.
+}
+
+impl Event {
+    pub fn expense(label: &str, year: usize, amount: f64, dollars: Dollars) -> Self {
+        Event {
+            label: label.to_string(),
+            year,
+            amount,
+            dollars,
+            every: None,
+        }
+    }
+
+    pub fn windfall(label: &str, year: usize, amount: f64, dollars: Dollars) -> Self {
+        Self::expense(label, year, -amount, dollars)
+    }
+
+    pub fn every(self, years: usize) -> Self {
+        assert!(years > 0);
+        Event {
+            every: Some(years),
+            ..self
+        }
+    }
+
+    // Whether the event is paid in the given period of retirement.
+    pub fn happens_in(&self, period: usize, periods_per_year: usize) -> bool {
+        let start = self.year * periods_per_year;
+        match self.every {
+            None => period == start,
+            Some(every) => {
+                period >= start && (period - start).is_multiple_of(every * periods_per_year)
+            }
+        }
+    }
+
+    // Add this event to annual expense vectors.
+    pub fn add_to(&self, real_expenses: &mut [f64], nominal_expenses: &mut [f64]) {
+        let expenses = match self.dollars {
+            Dollars::Real => real_expenses,
+            Dollars::Nominal => nominal_expenses,
+        };
+        for (year, expense) in expenses.iter_mut().enumerate() {
+            if self.happens_in(year, 1) {
+                *expense += self.amount;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dollars, Event};
+    use crate::dataset::Dataset;
+    use crate::time_series;
+    use crate::Backtest;
+
+    #[test]
+    fn events_are_expenses() {
+        let events = vec![
+            Event::expense("car", 2, 30., Dollars::Real).every(3),
+            Event::windfall("inheritance", 4, 100., Dollars::Nominal),
+        ];
+        let backtest = |real: Vec<f64>, nominal: Vec<f64>| {
+            Backtest::new(
+                1000.,
+                real,
+                nominal,
+                time_series::TOTAL_STOCK_MARKET.to_vec(),
+                time_series::TOTAL_BOND_MARKET.to_vec(),
+            )
+        };
+
+        let with_events = backtest(vec![40.; 9], vec![0.; 9]).with_events(events);
+        let mut real = vec![40.; 9];
+        real[2] += 30.;
+        real[5] += 30.;
+        real[8] += 30.;
+        let mut nominal = vec![0.; 9];
+        nominal[4] = -100.;
+        let by_hand = backtest(real, nominal);
+
+        let fractions = [0.7; 9];
+        assert_eq!(
+            with_events.single_run(&fractions, 100, false),
+            by_hand.single_run(&fractions, 100, false)
+        );
+        assert_eq!(with_events.event_labels(5), " [car]");
+        assert_eq!(with_events.event_labels(6), "");
+    }
+
+    #[test]
+    fn events_count_years_with_monthly_data() {
+        let monthly = Dataset::us_annual().spread_over_months();
+        let events = vec![
+            Event::expense("car", 2, 30., Dollars::Real).every(3),
+            Event::windfall("inheritance", 4, 100., Dollars::Nominal),
+        ];
+        let with_events = Backtest::from_dataset(1000., vec![4.; 108], vec![0.; 108], &monthly)
+            .with_events(events);
+        // Paid in full in the first month of years 2, 5, 8 and 4.
+        let mut real = vec![4.; 108];
+        real[24] += 30.;
+        real[60] += 30.;
+        real[96] += 30.;
+        let mut nominal = vec![0.; 108];
+        nominal[48] = -100.;
+        let by_hand = Backtest::from_dataset(1000., real, nominal, &monthly);
+
+        let fractions = [0.7; 108];
+        assert_eq!(
+            with_events.single_run(&fractions, 1200, false),
+            by_hand.single_run(&fractions, 1200, false)
+        );
+        assert_eq!(with_events.event_labels(60), " [car]");
+        assert_eq!(with_events.event_labels(5), "");
+    }
+}
@@ -0,0 +1,70 @@
+// Families of glide paths.  Each turns a few parameters into stock fractions
+// for `length` periods, in the form best_fractions and find_best_ranges take,
+// so a family can be searched over as well as used as is.
+
+// The same stock fraction throughout: [stocks].
+pub fn constant(values: &[f64], length: usize) -> Vec<f64> {
+    vec![values[0]; length]
+}
+
+// A straight line from the first period to the last: [start, end].
+pub fn linear(values: &[f64], length: usize) -> Vec<f64> {
+    (0..length)
+        .map(|i| {
+            let t = if length > 1 {
+                i as f64 / (length - 1) as f64
+            } else {
+                0.
+            };
+            values[0] + (values[1] - values[0]) * t
+        })
+        .collect()
+}
+
+// A straight line over the first `periods` periods, then flat:
+// [start, end, periods].  Rising equity glide paths, which start low to get
+// through a bad sequence early and then move back into stocks, are ramps.
+pub fn ramp(values: &[f64], length: usize) -> Vec<f64> {
+    let periods = values[2].round().max(1.) as usize;
+    (0..length)
+        .map(|i| {
+            let t = (i as f64 / periods as f64).min(1.);
+            values[0] + (values[1] - values[0]) * t
+        })
+        .collect()
+}
+
+pub type Family = fn(&[f64], usize) -> Vec<f64>;
+
+// Every family, with the names of its parameters.
+pub const FAMILIES: [(&str, Family, &[&str]); 3] = [
+    ("constant", constant, &["stocks"]),
+    ("linear", linear, &["start", "end"]),
+    ("ramp", ramp, &["start", "end", "periods"]),
+];
+
+// A family by name, and its parameter names.
+pub fn family(name: &str) -> Option<(Family, &'static [&'static str])> {
+    FAMILIES
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, f, params)| (*f, *params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{family, linear, ramp};
+
+    #[test]
+    fn families() {
+        let close = |a: Vec<f64>, b: &[f64]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-12)
+        };
+        assert!(close(linear(&[0.2, 0.8], 4), &[0.2, 0.4, 0.6, 0.8]));
+        assert!(close(ramp(&[0.3, 0.6, 3.], 5), &[0.3, 0.4, 0.5, 0.6, 0.6]));
+        let (constant, params) = family("constant").unwrap();
+        assert_eq!(constant(&[0.5], 3), vec![0.5; 3]);
+        assert_eq!(params, ["stocks"]);
+        assert!(family("tent").is_none());
+    }
+}
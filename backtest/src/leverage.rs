@@ -0,0 +1,156 @@
+use crate::time_series;
+
+// The real (inflation adjusted) cost of borrowing, in percent per period.
+pub enum BorrowRate {
+    Series(Vec<f64>),
+    // A fixed spread over a short term rate, e.g. a broker's margin rate
+    // over T-bills.
+    OverShortRate { short_rate: Vec<f64>, spread: f64 },
+}
+
+impl BorrowRate {
+    // A spread over the return of short term treasuries.  time_series has no
+    // T-bill series, and these are notes of a few years, so for a broker's
+    // rate over bills the spread needs to allow for the difference.
+    pub fn over_short_term_treasuries(spread: f64) -> Self {
+        BorrowRate::OverShortRate {
+            short_rate: time_series::SHORT_TERM_TREASURIES.to_vec(),
+            spread,
+        }
+    }
+
+    fn percent(&self) -> Vec<f64> {
+        match self {
+            BorrowRate::Series(rates) => rates.clone(),
+            BorrowRate::OverShortRate { short_rate, spread } => {
+                short_rate.iter().map(|r| r + spread).collect()
+            }
+        }
+    }
+}
+
+// Stock fractions above 1 buy stocks on margin.  The broker lends the excess,
+// and if equity falls below `maintenance` times the value of the stocks, it
+// sells enough to pay off the loan.
+#[derive(Clone)]
+pub struct Leverage {
+    pub max_ratio: f64,   // Largest allowed stock fraction, e.g. 2.0 for 2:1.
+    pub maintenance: f64, // Minimum equity / stocks, e.g. 0.25 under Reg T.
+    borrow_return: Vec<f64>,
+}
+
+impl Leverage {
+    pub fn new(borrow: BorrowRate, max_ratio: f64, maintenance: f64) -> Self {
+        assert!(max_ratio >= 1.);
+        assert!((0. ..1.).contains(&maintenance));
+        // Leverage above 1 / maintenance would be in a margin call the moment
+        // it was bought.
+        assert!(max_ratio * maintenance <= 1. + 1e-9);
+
+        Leverage {
+            max_ratio,
+            maintenance,
+            borrow_return: borrow.percent().iter().map(|r| 1. + r / 100.).collect(),
+        }
+    }
+
+    pub(crate) fn periods(&self) -> usize {
+        self.borrow_return.len()
+    }
+
+    // Grow `portfolio` of equity over one period with `stock_fraction` > 1
+    // invested in stocks.  Returns the new equity and whether there was a
+    // margin call.
+    //
+    // We only see returns at the end of each period, so a margin call is
+    // assumed to happen when the stock price falls through the level where
+    // equity hits maintenance.  The loan is repaid there and the rest of the
+    // period is spent unlevered.  Interest is charged for the whole period.
+    pub(crate) fn grow(
+        &self,
+        portfolio: f64,
+        stock_fraction: f64,
+        stock_return: f64,
+        index: usize,
+    ) -> (f64, bool) {
+        let borrowed = stock_fraction - 1.;
+        let interest = borrowed * (self.borrow_return[index] - 1.);
+        let levered = stock_fraction * stock_return - borrowed - interest;
+        if levered >= self.maintenance * stock_fraction * stock_return {
+            return (portfolio * levered, false);
+        }
+
+        // If the call comes when stocks have moved by a factor c, equity is
+        // then maintenance * stock_fraction * c, which earns stock_return / c
+        // for the rest of the period, so c drops out.
+        let unlevered = self.maintenance * stock_fraction * stock_return - interest;
+        (portfolio * unlevered, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BorrowRate, Leverage};
+    use crate::time_series;
+    use crate::Backtest;
+
+    fn backtest() -> Backtest {
+        Backtest::new(
+            1000.,
+            vec![40.; 10],
+            vec![0.; 10],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "outside [0, 1]")]
+    fn rejects_leverage_unless_enabled() {
+        backtest().single_run(&[1.5; 10], 0, false);
+    }
+
+    #[test]
+    fn borrowing_at_bond_rate_matches_implicit_leverage() {
+        let bonds = time_series::TOTAL_BOND_MARKET.to_vec();
+        let levered = backtest().with_leverage(Leverage::new(BorrowRate::Series(bonds), 1.5, 0.));
+
+        // The unchecked formula: bonds + 1.5 * (stocks - bonds).
+        let start = 1950 - time_series::FIRST_YEAR;
+        let mut expected = 1000.;
+        for i in 0..10 {
+            let stocks = time_series::TOTAL_STOCK_MARKET[start + i] / 100.;
+            let bonds = time_series::TOTAL_BOND_MARKET[start + i] / 100.;
+            expected = (expected - 40.) * (1. + bonds + 1.5 * (stocks - bonds));
+        }
+        assert!((levered.single_run(&[1.5; 10], start, false) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn margin_call_sells_at_maintenance() {
+        let backtest = Backtest::new(
+            1000.,
+            vec![0.],
+            vec![0.],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+        .with_leverage(Leverage::new(
+            BorrowRate::over_short_term_treasuries(2.),
+            2.,
+            0.4,
+        ));
+
+        // 2:1 leverage through 1931's -37.87% leaves equity at 24% of stocks,
+        // so the broker sold out on the way down, when it hit 40%.
+        let start = 1931 - time_series::FIRST_YEAR;
+        let interest = (time_series::SHORT_TERM_TREASURIES[start] + 2.) / 100.;
+        let expected = 1000. * (0.4 * 2. * (1. - 0.3787) - interest);
+        assert!((backtest.single_run(&[2.], start, false) - expected).abs() < 1e-6);
+
+        // 1932 was barely positive, so the loan survives the year.
+        let levered = 1000.
+            * (2. * 1.0126 - 1. - (time_series::SHORT_TERM_TREASURIES[start + 1] + 2.) / 100.);
+        assert!((backtest.single_run(&[2.], start + 1, false) - levered).abs() < 1e-6);
+    }
+}
@@ -1,5 +1,35 @@
 use std::time::Instant;
 
+pub mod annuity;
+pub mod attribution;
+pub mod bucket;
+pub mod compare;
+pub mod config;
+pub mod dataset;
+pub mod drawdown;
+pub mod events;
+pub mod glide;
+pub mod leverage;
+pub mod loan;
+pub mod monte_carlo;
+pub mod parametric;
+pub mod pool;
+pub mod pwr;
+pub mod regime;
+pub mod report;
+pub mod rmd;
+pub mod scenario;
+pub mod spending;
+pub mod stress;
+pub mod svg;
+pub mod windows;
+
+use annuity::{Annuity, Purchased};
+use dataset::Dataset;
+use events::{Dollars, Event};
+use leverage::Leverage;
+use windows::{Sequence, StartYears};
+
 #[cfg(test)]
 mod tests {
     struct SimpleBacktest {
@@ -18,6 +48,7 @@ mod tests {
     }
 
     impl SimpleBacktest {
+        #[allow(clippy::too_many_arguments)]
         fn new(
             start_portfolio: f64,
             real_expenses: Vec<f64>, // real = inflation adjusted, e.g. consumer goods.
@@ -108,9 +139,9 @@ mod tests {
     #[test]
     fn real_only() {
         compare(
-            &vec![1., 2., 3., 4., 5.],
-            &vec![0.; 5],
-            &vec![0.4, 0.5, 0.6, 0.7, 0.8],
+            &[1., 2., 3., 4., 5.],
+            &[0.; 5],
+            &[0.4, 0.5, 0.6, 0.7, 0.8],
             1929,
         );
     }
@@ -118,14 +149,22 @@ mod tests {
     #[test]
     fn real_and_nominal_only() {
         compare(
-            &vec![1., 22., 53., -4., -11.],
-            &vec![100., 123., 99., -54., 978.],
-            &vec![0.4, 0.5, 0.45, 0.9, 0.23],
+            &[1., 22., 53., -4., -11.],
+            &[100., 123., 99., -54., 978.],
+            &[0.4, 0.5, 0.45, 0.9, 0.23],
             1965,
         );
     }
+
+    #[test]
+    fn percentile() {
+        assert_eq!(super::percentile(&[3., 1., 2., 5., 4.], 0.), 1.);
+        assert_eq!(super::percentile(&[3., 1., 2., 5., 4.], 0.5), 3.);
+        assert_eq!(super::percentile(&[3., 1., 2., 5., 4.], 1.), 5.);
+    }
 }
 
+#[allow(clippy::approx_constant)]
 pub mod time_series {
     // From Simba's backtesting spreadsheet
     // https://www.bogleheads.org/wiki/Simba%27s_backtesting_spreadsheet
@@ -300,9 +339,46 @@ fn update(ranges: &[Range], values: &mut [f64]) -> bool {
 
         values[i] = this.start;
     }
-    return true;
+    true
+}
+
+// Try every combination of values in `ranges`, and return the one for which
+// `score` is largest, with its score and whatever else `score` returned.
+pub fn grid_search<T, F: FnMut(&[f64]) -> (f64, T)>(
+    ranges: &[Range],
+    mut score: F,
+) -> (Vec<f64>, f64, T) {
+    // Set all initial values to starts.
+    let mut values: Vec<f64> = ranges.iter().map(|r| r.start).collect();
+
+    let mut best_score = f64::NEG_INFINITY;
+    let mut best_values = values.clone();
+    let mut best = None;
+    loop {
+        let (value, extra) = score(&values);
+        if value > best_score || best.is_none() {
+            best_score = value;
+            best_values = values.clone();
+            best = Some(extra);
+        }
+
+        if update(ranges, &mut values) {
+            break;
+        }
+    }
+    (best_values, best_score, best.unwrap())
+}
+
+// The p'th quantile, 0 being the minimum and 1 the maximum, of a non-empty
+// slice.  The nearest value, not interpolated.
+pub fn percentile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
 }
 
+// A period is a year for annual data and a month for monthly data.  Expenses,
+// stock fractions and year offsets are all counted in periods.
 pub struct Backtest {
     start_portfolio: f64,
     real_expenses: Vec<f64>, // real = inflation adjusted, e.g. consumer goods.
@@ -310,6 +386,14 @@ pub struct Backtest {
     bond_return: Vec<f64>,
     delta_return: Vec<f64>,
     inflation: Vec<f64>,
+    first_year: usize,
+    first_month: usize,
+    periods_per_year: usize,
+    leverage: Option<Leverage>,
+    annuities: Vec<Annuity>,
+    events: Vec<Event>,
+    start_years: StartYears,
+    sequence: Sequence,
 }
 
 impl Backtest {
@@ -320,21 +404,44 @@ impl Backtest {
         stonks: Vec<f64>,
         bonds: Vec<f64>,
     ) -> Self {
-        assert_eq!(real_expenses.len(), nominal_expenses.len());
-
         assert_eq!(stonks.len(), time_series::YEARS);
         assert_eq!(bonds.len(), time_series::YEARS);
 
+        Self::from_dataset(
+            start_portfolio,
+            real_expenses,
+            nominal_expenses,
+            &Dataset {
+                stocks: stonks,
+                bonds,
+                ..Dataset::us_annual()
+            },
+        )
+    }
+
+    // Expenses are per period of the dataset, e.g. monthly for monthly data.
+    pub fn from_dataset(
+        start_portfolio: f64,
+        real_expenses: Vec<f64>,
+        nominal_expenses: Vec<f64>,
+        dataset: &Dataset,
+    ) -> Self {
+        assert_eq!(real_expenses.len(), nominal_expenses.len());
+
+        assert_eq!(dataset.bonds.len(), dataset.len());
+        assert_eq!(dataset.inflation.len(), dataset.len());
+        assert!(real_expenses.len() <= dataset.len());
+
         let mut delta_return: Vec<f64> = Vec::new();
         let mut bond_return: Vec<f64> = Vec::new();
-        for i in 0..time_series::YEARS {
-            bond_return.push(1.0 + bonds[i] / 100.0);
-            delta_return.push((stonks[i] - bonds[i]) / 100.0);
+        for i in 0..dataset.len() {
+            bond_return.push(1.0 + dataset.bonds[i] / 100.0);
+            delta_return.push((dataset.stocks[i] - dataset.bonds[i]) / 100.0);
         }
 
         let mut inflation: Vec<f64> = Vec::new();
-        for i in 0..time_series::YEARS {
-            inflation.push(1.0 + time_series::INFLATION[i] / 100.0);
+        for i in 0..dataset.len() {
+            inflation.push(1.0 + dataset.inflation[i] / 100.0);
         }
 
         Backtest {
@@ -344,37 +451,298 @@ impl Backtest {
             bond_return,
             delta_return,
             inflation,
+            first_year: dataset.first_year,
+            first_month: dataset.first_month,
+            periods_per_year: dataset.periods_per_year,
+            leverage: None,
+            annuities: Vec::new(),
+            events: Vec::new(),
+            start_years: StartYears::All,
+            sequence: Sequence::Historical,
+        }
+    }
+
+    // Add one-off or recurring cash flows to the expenses of every window.
+    // They're labelled in verbose output.
+    pub fn with_events(mut self, events: Vec<Event>) -> Self {
+        self.events.extend(events);
+        self
+    }
+
+    // Real dollars of events in period i, on top of the expenses.  Events are
+    // kept apart from real_expenses so that analyses which replace the
+    // expenses, such as perfect_withdrawal, still pay them.
+    fn event_expenses(&self, i: usize, inflation_factor: f64) -> f64 {
+        self.events
+            .iter()
+            .filter(|e| e.happens_in(i, self.periods_per_year))
+            .map(|e| match e.dollars {
+                Dollars::Real => e.amount,
+                Dollars::Nominal => e.amount / inflation_factor,
+            })
+            .sum()
+    }
+
+    // Labels of the events in period i, for verbose output.
+    fn event_labels(&self, i: usize) -> String {
+        let labels: Vec<&str> = self
+            .events
+            .iter()
+            .filter(|e| e.happens_in(i, self.periods_per_year))
+            .map(|e| e.label.as_str())
+            .collect();
+        if labels.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", labels.join(", "))
+        }
+    }
+
+    // Allow stock fractions above 1, up to leverage.max_ratio.  Without this,
+    // stock fractions must be in [0, 1].
+    pub fn with_leverage(mut self, leverage: Leverage) -> Self {
+        assert_eq!(leverage.periods(), self.bond_return.len());
+        self.leverage = Some(leverage);
+        self
+    }
+
+    // Only run windows starting in these calendar years, in every analysis
+    // over historical windows.
+    pub fn with_start_years(mut self, start_years: StartYears) -> Self {
+        self.start_years = start_years;
+        self
+    }
+
+    // How windows run through the data: forwards as it happened, wrapping
+    // around from the last period to the first, or backwards.
+    pub fn with_sequence(mut self, sequence: Sequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    // Index into the data of period i of the window starting at year_offset.
+    // Every run goes through this, so every analysis follows the sequence.
+    fn index(&self, year_offset: usize, i: usize) -> usize {
+        match self.sequence {
+            Sequence::Historical => year_offset + i,
+            Sequence::Circular => (year_offset + i) % self.bond_return.len(),
+            Sequence::Reversed => year_offset - i,
+        }
+    }
+
+    // Like index, for the period `back` periods before period i, following
+    // the sequence back past the start of the window as far as the data goes.
+    fn index_back(&self, year_offset: usize, i: usize, back: usize) -> Option<usize> {
+        if back <= i {
+            return Some(self.index(year_offset, i - back));
+        }
+        let before = back - i;
+        let periods = self.bond_return.len();
+        match self.sequence {
+            Sequence::Historical => year_offset.checked_sub(before),
+            Sequence::Circular => Some((year_offset + periods - before % periods) % periods),
+            Sequence::Reversed => Some(year_offset + before).filter(|&index| index < periods),
+        }
+    }
+
+    // Whether a window `length` periods long starting at year_offset fits in
+    // the data.
+    fn window_fits(&self, year_offset: usize, length: usize) -> bool {
+        let periods = self.bond_return.len();
+        year_offset < periods
+            && match self.sequence {
+                Sequence::Historical => year_offset + length <= periods,
+                Sequence::Circular => length <= periods,
+                Sequence::Reversed => length <= year_offset + 1,
+            }
+    }
+
+    // Start offsets of the windows `length` periods long that fit in the data
+    // and start in an allowed year.
+    fn window_offsets(&self, length: usize) -> Vec<usize> {
+        (0..self.bond_return.len())
+            .filter(|offset| self.window_fits(*offset, length))
+            .filter(|offset| self.start_years.contains(self.year_of(*offset)))
+            .collect()
+    }
+
+    // Buy an annuity in every window.  Its income offsets expenses.  Only
+    // annual datasets are supported.
+    pub fn with_annuity(mut self, annuity: Annuity) -> Self {
+        assert_eq!(self.periods_per_year, 1);
+        assert_eq!(annuity.years(), self.bond_return.len());
+        assert!(annuity.purchase_year < self.real_expenses.len());
+        self.annuities.push(annuity);
+        self
+    }
+
+    // Buy any annuities due in year i of a run starting at year_offset, and
+    // return the year's total annuity income in real dollars.
+    fn annuity_income(
+        &self,
+        purchased: &mut [Option<Purchased>],
+        i: usize,
+        year_offset: usize,
+        portfolio: &mut f64,
+        inflation_factor: f64,
+    ) -> f64 {
+        let mut income = 0.;
+        for (annuity, bought) in self.annuities.iter().zip(purchased.iter_mut()) {
+            if annuity.purchase_year == i {
+                let past: Vec<f64> = (1..=10)
+                    .rev()
+                    .filter_map(|back| self.index_back(year_offset, i, back))
+                    .map(|index| self.inflation[index])
+                    .collect();
+                *bought = Some(Purchased::buy(
+                    annuity,
+                    portfolio,
+                    self.index(year_offset, i),
+                    &past,
+                    inflation_factor,
+                ));
+            }
+            if let Some(bought) = bought {
+                income += bought.income(annuity.payout, inflation_factor);
+            }
+        }
+        income
+    }
+
+    fn check_fraction(&self, stock_fraction: f64) {
+        let max = self.leverage.as_ref().map_or(1., |l| l.max_ratio);
+        assert!(
+            (0. ..=max + 1e-9).contains(&stock_fraction),
+            "stock fraction {} outside [0, {}]",
+            stock_fraction,
+            max
+        );
+    }
+
+    // Rebalance to stock_fraction, then a period passes.  Returns the new
+    // portfolio value and whether there was a margin call.
+    fn grow(&self, portfolio: f64, stock_fraction: f64, index: usize) -> (f64, bool) {
+        match &self.leverage {
+            Some(leverage) if stock_fraction > 1. => leverage.grow(
+                portfolio,
+                stock_fraction,
+                self.bond_return[index] + self.delta_return[index],
+                index,
+            ),
+            _ => (
+                portfolio * (self.bond_return[index] + stock_fraction * self.delta_return[index]),
+                false,
+            ),
+        }
+    }
+
+    // Calendar year of the period at the given offset.  usize::MAX, which
+    // worst_offsets returns when there's no second worst window, stays
+    // usize::MAX.
+    pub fn year_of(&self, offset: usize) -> usize {
+        if offset == usize::MAX {
+            return usize::MAX;
+        }
+        self.date_of(offset).0
+    }
+
+    // Calendar (year, month) of the period at the given offset.
+    pub fn date_of(&self, offset: usize) -> (usize, usize) {
+        let months = self.first_month - 1 + offset * 12 / self.periods_per_year;
+        (self.first_year + months / 12, months % 12 + 1)
+    }
+
+    fn label(&self, offset: usize) -> String {
+        let (year, month) = self.date_of(offset);
+        if self.periods_per_year == 1 {
+            format!("{}", year)
+        } else {
+            format!("{}-{:02}", year, month)
         }
     }
 
     pub fn single_run(&self, stock_fractions: &[f64], year_offset: usize, verbose: bool) -> f64 {
-        assert_eq!(stock_fractions.len(), self.real_expenses.len());
+        self.run(
+            &self.real_expenses,
+            stock_fractions,
+            year_offset,
+            verbose,
+            None,
+        )
+    }
+
+    // The portfolio value at the end of every period of the window, in real
+    // dollars.
+    pub fn portfolio_path(&self, stock_fractions: &[f64], year_offset: usize) -> Vec<f64> {
+        let mut path = Vec::with_capacity(stock_fractions.len());
+        self.run(
+            &self.real_expenses,
+            stock_fractions,
+            year_offset,
+            false,
+            Some(&mut path),
+        );
+        path
+    }
+
+    // single_run with different real expenses, optionally recording the path.
+    // They may be shorter than self.real_expenses, for a shorter retirement.
+    fn run(
+        &self,
+        real_expenses: &[f64],
+        stock_fractions: &[f64],
+        year_offset: usize,
+        verbose: bool,
+        mut path: Option<&mut Vec<f64>>,
+    ) -> f64 {
+        assert_eq!(stock_fractions.len(), real_expenses.len());
+        assert!(real_expenses.len() <= self.real_expenses.len());
+        for f in stock_fractions {
+            self.check_fraction(*f);
+        }
         let mut portfolio = self.start_portfolio;
+        let mut purchased: Vec<Option<Purchased>> = self.annuities.iter().map(|_| None).collect();
 
         let mut inflation_factor = 1.0;
-        for i in 0..stock_fractions.len() {
+        for (i, stock_fraction) in stock_fractions.iter().enumerate() {
             // Remove expenses at the start of the year.
-            let expenses = self.nominal_expenses[i] / inflation_factor + self.real_expenses[i];
+            let income = self.annuity_income(
+                &mut purchased,
+                i,
+                year_offset,
+                &mut portfolio,
+                inflation_factor,
+            );
+            let expenses = self.nominal_expenses[i] / inflation_factor
+                + real_expenses[i]
+                + self.event_expenses(i, inflation_factor)
+                - income;
             portfolio -= expenses;
 
             // Rebalance, then a year passes.
-            portfolio = portfolio
-                * (self.bond_return[year_offset + i]
-                    + stock_fractions[i] * self.delta_return[year_offset + i]);
+            let margin_call;
+            (portfolio, margin_call) =
+                self.grow(portfolio, *stock_fraction, self.index(year_offset, i));
 
-            inflation_factor *= self.inflation[year_offset + i];
+            inflation_factor *= self.inflation[self.index(year_offset, i)];
+            if let Some(path) = path.as_mut() {
+                path.push(portfolio);
+            }
             if verbose {
                 println!(
-                    "{}: expenses ${}k, portfolio value ${:.3}k",
-                    i + year_offset + time_series::FIRST_YEAR,
+                    "{}: expenses ${}k, portfolio value ${:.3}k{}{}",
+                    self.label(self.index(year_offset, i)),
                     expenses.round() / 1e3,
-                    portfolio.round() / 1e3
+                    portfolio.round() / 1e3,
+                    if margin_call { ", margin call" } else { "" },
+                    self.event_labels(i)
                 );
             }
         }
         portfolio
     }
 
+    // f(portfolio, periods_left) returns the stock fraction for the coming period.
     pub fn single_run_general<F: Fn(f64, usize) -> f64>(
         &self,
         f: F,
@@ -382,48 +750,68 @@ impl Backtest {
         verbose: bool,
     ) -> f64 {
         let mut portfolio = self.start_portfolio;
+        let mut purchased: Vec<Option<Purchased>> = self.annuities.iter().map(|_| None).collect();
 
         let mut inflation_factor = 1.0;
         for i in 0..self.real_expenses.len() {
+            let income = self.annuity_income(
+                &mut purchased,
+                i,
+                year_offset,
+                &mut portfolio,
+                inflation_factor,
+            );
             // Get stock fraction.
             let years_left = self.real_expenses.len() - i;
             let stock_fraction = f(portfolio, years_left);
+            self.check_fraction(stock_fraction);
             // Remove expenses at the start of the year.
-            let expenses = self.nominal_expenses[i] / inflation_factor + self.real_expenses[i];
+            let expenses = self.nominal_expenses[i] / inflation_factor
+                + self.real_expenses[i]
+                + self.event_expenses(i, inflation_factor)
+                - income;
             let expense_ratio = expenses / portfolio;
             let allowable = 1. / years_left as f64;
             portfolio -= expenses;
 
             // Rebalance, then a year passes.
-            portfolio = portfolio
-                * (self.bond_return[year_offset + i]
-                    + stock_fraction * self.delta_return[year_offset + i]);
+            let margin_call;
+            (portfolio, margin_call) =
+                self.grow(portfolio, stock_fraction, self.index(year_offset, i));
 
-            inflation_factor *= self.inflation[year_offset + i];
+            inflation_factor *= self.inflation[self.index(year_offset, i)];
             if verbose {
                 println!(
-                    "{}: expenses ${}k, portfolio value ${:.3}k, stocks: {}%, {:.2}% vs {:.2}%",
-                    i + year_offset + time_series::FIRST_YEAR,
+                    "{}: expenses ${}k, portfolio value ${:.3}k, stocks: {}%, {:.2}% vs {:.2}%{}{}",
+                    self.label(self.index(year_offset, i)),
                     expenses.round() / 1e3,
                     portfolio.round() / 1e3,
                     (stock_fraction * 1000.).round() / 10.,
                     expense_ratio * 100.,
-                    allowable * 100.
+                    allowable * 100.,
+                    if margin_call { ", margin call" } else { "" },
+                    self.event_labels(i)
                 );
             }
         }
         portfolio
     }
 
-    pub fn worst_year(&self, stock_fractions: &Vec<f64>) -> (usize, f64, usize, f64) {
+    // Runs every historical window and returns the offsets and ending values
+    // of the worst and second worst.
+    //
+    // `run` returns None for windows that can't be run, e.g. for lack of data.
+    fn worst_offsets<F: Fn(usize) -> Option<f64>>(&self, run: F) -> (usize, f64, usize, f64) {
         let mut worst_value = f64::INFINITY;
         let mut worst_year = usize::MAX;
 
         let mut second_worst_value = f64::INFINITY;
         let mut second_worst_year = usize::MAX;
 
-        for year_offset in 0..(self.bond_return.len() - self.real_expenses.len() + 1) {
-            let value = self.single_run(stock_fractions, year_offset, false);
+        for year_offset in self.window_offsets(self.real_expenses.len()) {
+            let Some(value) = run(year_offset) else {
+                continue;
+            };
             assert!(value < 1e12);
             if value < worst_value {
                 second_worst_year = worst_year;
@@ -435,11 +823,31 @@ impl Backtest {
                 second_worst_year = year_offset;
             }
         }
-        assert!(worst_year <= time_series::YEARS);
+        assert!(
+            worst_year < self.bond_return.len(),
+            "no windows to run in the allowed start years"
+        );
+        (
+            worst_year,
+            worst_value,
+            second_worst_year,
+            second_worst_value,
+        )
+    }
+
+    // Like worst_year, but returns period offsets rather than calendar years.
+    // With monthly data, use date_of() to find the starting month.
+    pub fn worst_start(&self, stock_fractions: &[f64]) -> (usize, f64, usize, f64) {
+        self.worst_offsets(|year_offset| Some(self.single_run(stock_fractions, year_offset, false)))
+    }
+
+    pub fn worst_year(&self, stock_fractions: &[f64]) -> (usize, f64, usize, f64) {
+        let (worst_year, worst_value, second_worst_year, second_worst_value) =
+            self.worst_start(stock_fractions);
         (
-            worst_year + time_series::FIRST_YEAR,
+            self.year_of(worst_year),
             worst_value,
-            second_worst_year + time_series::FIRST_YEAR,
+            self.year_of(second_worst_year),
             second_worst_value,
         )
     }
@@ -448,70 +856,33 @@ impl Backtest {
         &self,
         get_stock_fraction: F,
     ) -> (usize, f64, usize, f64) {
-        let mut worst_value = f64::INFINITY;
-        let mut worst_year = usize::MAX;
-
-        let mut second_worst_value = f64::INFINITY;
-        let mut second_worst_year = usize::MAX;
-
-        for year_offset in 0..(self.bond_return.len() - self.real_expenses.len() + 1) {
-            let value = self.single_run_general(&get_stock_fraction, year_offset, false);
-            assert!(value < 1e12);
-            if value < worst_value {
-                second_worst_year = worst_year;
-                second_worst_value = worst_value;
-                worst_value = value;
-                worst_year = year_offset;
-            } else if value < second_worst_value {
-                second_worst_value = value;
-                second_worst_year = year_offset;
-            }
-        }
-        assert!(worst_year <= time_series::YEARS);
+        let (worst_year, worst_value, second_worst_year, second_worst_value) =
+            self.worst_offsets(|year_offset| {
+                Some(self.single_run_general(&get_stock_fraction, year_offset, false))
+            });
         (
-            worst_year + time_series::FIRST_YEAR,
+            self.year_of(worst_year),
             worst_value,
-            second_worst_year + time_series::FIRST_YEAR,
+            self.year_of(second_worst_year),
             second_worst_value,
         )
     }
-
     pub fn best_fractions(
         &self,
         ranges: &[Range],
         get_fractions: fn(&[f64], usize) -> Vec<f64>,
         length: usize,
     ) -> (Vec<f64>, f64, usize, f64, usize) {
-        let mut values: Vec<f64> = Vec::new();
-        // Set all initial values to starts.
-
-        for r in ranges {
-            values.push(r.start);
-        }
-
-        let mut best_end_portfolio: f64 = f64::NEG_INFINITY;
-        let mut best_year: usize = 0;
-        let mut best_values: Vec<f64> = Vec::new();
-        let mut second_best_end_portfolio: f64 = f64::NEG_INFINITY;
-        let mut second_best_year: usize = 0;
-
-        loop {
-            let stock_fractions = get_fractions(&values, length);
+        let (
+            best_values,
+            best_end_portfolio,
+            (best_year, second_best_end_portfolio, second_best_year),
+        ) = grid_search(ranges, |values| {
+            let stock_fractions = get_fractions(values, length);
             let (year, end_portfolio, second_year, second_end_portfolio) =
                 self.worst_year(&stock_fractions);
-            if end_portfolio > best_end_portfolio {
-                best_end_portfolio = end_portfolio;
-                best_year = year;
-                best_values = values.clone();
-
-                second_best_end_portfolio = second_end_portfolio;
-                second_best_year = second_year;
-            }
-
-            if update(&ranges, &mut values) {
-                break;
-            }
-        }
+            (end_portfolio, (year, second_end_portfolio, second_year))
+        });
         (
             best_values,
             best_end_portfolio,
@@ -549,8 +920,8 @@ impl Backtest {
             elapsed_micros as f64 / 1000.,
             best_values[0] * 100.0
         );
-        for i in 1..best_values.len() {
-            print!(" to {:.0}%", best_values[i] * 100.0);
+        for value in &best_values[1..] {
+            print!(" to {:.0}%", value * 100.0);
         }
 
         println!(
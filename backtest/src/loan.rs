@@ -0,0 +1,150 @@
+// Fixed rate loans, e.g. mortgages, as schedules of nominal expenses.
+//
+// Years are counted from the start of retirement, the same as the expense
+// vectors, so a mortgage taken out ten years before retiring has start -10.
+// Payments are annual, made at the start of each year like other expenses,
+// and the first is due one year after the loan is taken out.
+pub struct Loan {
+    pub principal: f64,
+    pub rate: f64, // Nominal annual interest rate, in percent.
+    pub term: usize,
+    pub start: isize,
+    pub extra_principal: f64,       // Prepaid with each payment.
+    pub payoff_year: Option<isize>, // Pay off whatever's left in this year.
+}
+
+// One year of a loan's amortization schedule.
+#[derive(Clone, Copy, Debug)]
+pub struct LoanYear {
+    pub year: isize,
+    pub payment: f64,
+    pub interest: f64,
+    pub principal: f64,
+    pub balance: f64, // Left after the payment.
+}
+
+impl Loan {
+    pub fn new(principal: f64, rate: f64, term: usize, start: isize) -> Self {
+        assert!(principal >= 0. && term > 0);
+        Loan {
+            principal,
+            rate,
+            term,
+            start,
+            extra_principal: 0.,
+            payoff_year: None,
+        }
+    }
+
+    // Pay off the remaining balance at the start of retirement rather than
+    // keep the money invested.
+    pub fn paid_off_at_retirement(self) -> Self {
+        Loan {
+            payoff_year: Some(0),
+            ..self
+        }
+    }
+
+    // The regular payment, without any extra principal.
+    pub fn payment(&self) -> f64 {
+        let r = self.rate / 100.;
+        if r == 0. {
+            self.principal / self.term as f64
+        } else {
+            self.principal * r / (1. - (1. + r).powi(-(self.term as i32)))
+        }
+    }
+
+    pub fn amortization(&self) -> Vec<LoanYear> {
+        assert!(self.extra_principal >= 0., "negative extra principal");
+        let r = self.rate / 100.;
+        let payment = self.payment();
+        let mut balance = self.principal;
+        let mut years = Vec::new();
+        let mut year = self.start;
+        while balance > 1e-9 {
+            year += 1;
+            let interest = balance * r;
+            // Otherwise the balance never goes down.
+            assert!(
+                payment + self.extra_principal > interest,
+                "payment doesn't cover the interest"
+            );
+            let mut principal = (payment - interest + self.extra_principal).min(balance);
+            if self.payoff_year.is_some_and(|y| year >= y) {
+                principal = balance;
+            }
+            balance -= principal;
+            years.push(LoanYear {
+                year,
+                payment: interest + principal,
+                interest,
+                principal,
+                balance,
+            });
+        }
+        years
+    }
+
+    // Nominal payments in each of the first `length` years of retirement.
+    pub fn schedule(&self, length: usize) -> Vec<f64> {
+        let mut payments = vec![0.; length];
+        self.add_to(&mut payments);
+        payments
+    }
+
+    // Add the payments made during retirement to a nominal expense vector.
+    pub fn add_to(&self, nominal_expenses: &mut [f64]) {
+        for year in self.amortization() {
+            if year.year >= 0 && (year.year as usize) < nominal_expenses.len() {
+                nominal_expenses[year.year as usize] += year.payment;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Loan;
+
+    #[test]
+    fn amortizes_to_zero() {
+        let loan = Loan::new(100_000., 6., 30, 0);
+        assert!((loan.payment() - 7264.89).abs() < 0.01);
+
+        let years = loan.amortization();
+        assert_eq!(years.len(), 30);
+        assert_eq!(years[0].year, 1);
+        assert!(years.last().unwrap().balance.abs() < 1e-6);
+        let principal: f64 = years.iter().map(|y| y.principal).sum();
+        assert!((principal - 100_000.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn prepayment_and_payoff() {
+        let mut loan = Loan::new(100_000., 6., 30, -10);
+        loan.extra_principal = 2000.;
+        assert!(loan.amortization().len() < 30 - 5);
+
+        // Paying off at retirement replaces 21 years of payments with one
+        // lump sum: the balance after the ninth payment, plus a year's
+        // interest.
+        let loan = Loan::new(100_000., 6., 30, -10);
+        let balance = loan.amortization()[8].balance;
+        let schedule = loan.schedule(30);
+        assert!((schedule[20] - loan.payment()).abs() < 1e-6);
+        assert_eq!(schedule[21], 0.);
+
+        let schedule = loan.paid_off_at_retirement().schedule(30);
+        assert!((schedule[0] - balance * 1.06).abs() < 1e-6);
+        assert!(schedule[1..].iter().all(|p| *p == 0.));
+    }
+
+    #[test]
+    #[should_panic(expected = "negative extra principal")]
+    fn negative_prepayment() {
+        let mut loan = Loan::new(100_000., 6., 30, 0);
+        loan.extra_principal = -loan.payment();
+        loan.amortization();
+    }
+}
@@ -0,0 +1,247 @@
+use crate::dataset::Dataset;
+use crate::windows::{Sequence, StartYears};
+use crate::{percentile, Backtest};
+
+// Monte Carlo: instead of the historical windows, run the plan over many
+// made up return sequences.  A ReturnGenerator makes the sequences and
+// Backtest::simulate runs them.  Everything is seeded, so a simulation can be
+// rerun exactly.
+
+// A small, fast, seedable random number generator (xoshiro256**).  Not for
+// cryptography.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed over the state with splitmix64, as the xoshiro
+        // authors recommend.
+        let mut x = seed;
+        let mut state = [0; 4];
+        for s in &mut state {
+            x = x.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            *s = z ^ (z >> 31);
+        }
+        Rng { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    // Uniform in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in 0..n.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.uniform() * n as f64) as usize
+    }
+
+    // Standard normal, by Box-Muller.
+    pub fn normal(&mut self) -> f64 {
+        let u = 1. - self.uniform(); // In (0, 1], so the log is finite.
+        let v = self.uniform();
+        (-2. * u.ln()).sqrt() * (2. * std::f64::consts::PI * v).cos()
+    }
+}
+
+// One made up market history: real stock and bond returns and inflation, in
+// percent, like a Dataset's.
+#[derive(Clone, Debug, Default)]
+pub struct Sample {
+    pub stocks: Vec<f64>,
+    pub bonds: Vec<f64>,
+    pub inflation: Vec<f64>,
+}
+
+pub trait ReturnGenerator {
+    fn periods_per_year(&self) -> usize;
+
+    // A sequence `periods` long.
+    fn generate(&self, rng: &mut Rng, periods: usize) -> Sample;
+}
+
+// Resample history in blocks of consecutive periods, wrapping around at the
+// end.  Stocks, bonds and inflation are drawn together, so their correlation
+// is kept, and blocks keep some of the year to year momentum and mean
+// reversion.  A block of 1 is the plain bootstrap.
+pub struct Bootstrap {
+    dataset: Dataset,
+    block: usize, // In periods.
+}
+
+impl Bootstrap {
+    // `block_years` is the block length in years.
+    pub fn new(dataset: &Dataset, block_years: usize) -> Self {
+        assert!(block_years > 0);
+        assert!(!dataset.is_empty());
+        Bootstrap {
+            dataset: dataset.clone(),
+            block: block_years * dataset.periods_per_year,
+        }
+    }
+}
+
+impl ReturnGenerator for Bootstrap {
+    fn periods_per_year(&self) -> usize {
+        self.dataset.periods_per_year
+    }
+
+    fn generate(&self, rng: &mut Rng, periods: usize) -> Sample {
+        let d = &self.dataset;
+        let mut sample = Sample::default();
+        while sample.stocks.len() < periods {
+            let start = rng.below(d.len());
+            for i in 0..self.block.min(periods - sample.stocks.len()) {
+                let index = (start + i) % d.len();
+                sample.stocks.push(d.stocks[index]);
+                sample.bonds.push(d.bonds[index]);
+                sample.inflation.push(d.inflation[index]);
+            }
+        }
+        sample
+    }
+}
+
+// The ending value of every trial.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub ending_values: Vec<f64>,
+}
+
+impl Simulation {
+    // Fraction of trials that end with money left.
+    pub fn success_rate(&self) -> f64 {
+        let successes = self.ending_values.iter().filter(|v| **v >= 0.).count();
+        successes as f64 / self.ending_values.len() as f64
+    }
+
+    pub fn percentile(&self, p: f64) -> f64 {
+        percentile(&self.ending_values, p)
+    }
+}
+
+impl Backtest {
+    // This plan on a made up history.  Leverage and annuities are tied to
+    // the historical dataset, so they aren't supported.
+    pub fn with_sample(&self, sample: &Sample) -> Backtest {
+        assert!(self.leverage.is_none());
+        assert!(self.annuities.is_empty());
+        assert_eq!(sample.stocks.len(), self.real_expenses.len());
+        Backtest {
+            start_portfolio: self.start_portfolio,
+            real_expenses: self.real_expenses.clone(),
+            nominal_expenses: self.nominal_expenses.clone(),
+            bond_return: sample.bonds.iter().map(|b| 1. + b / 100.).collect(),
+            delta_return: sample
+                .stocks
+                .iter()
+                .zip(&sample.bonds)
+                .map(|(s, b)| (s - b) / 100.)
+                .collect(),
+            inflation: sample.inflation.iter().map(|i| 1. + i / 100.).collect(),
+            first_year: 0,
+            first_month: 1,
+            periods_per_year: self.periods_per_year,
+            leverage: None,
+            annuities: Vec::new(),
+            events: self.events.clone(),
+            start_years: StartYears::All,
+            sequence: Sequence::Historical,
+        }
+    }
+
+    pub fn simulate<G: ReturnGenerator + ?Sized>(
+        &self,
+        generator: &G,
+        stock_fractions: &[f64],
+        trials: usize,
+        seed: u64,
+    ) -> Simulation {
+        assert_eq!(generator.periods_per_year(), self.periods_per_year);
+        let mut rng = Rng::new(seed);
+        let ending_values = (0..trials)
+            .map(|_| {
+                let sample = generator.generate(&mut rng, self.real_expenses.len());
+                self.with_sample(&sample)
+                    .single_run(stock_fractions, 0, false)
+            })
+            .collect();
+        Simulation { ending_values }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bootstrap, ReturnGenerator, Rng};
+    use crate::dataset::Dataset;
+    use crate::Backtest;
+
+    #[test]
+    fn rng() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let draws: Vec<u64> = (0..5).map(|_| a.next_u64()).collect();
+        assert_eq!(draws, (0..5).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(Rng::new(43).next_u64(), draws[0]);
+
+        let n = 100_000;
+        let normals: Vec<f64> = (0..n).map(|_| a.normal()).collect();
+        let mean = normals.iter().sum::<f64>() / n as f64;
+        let variance = normals.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+        assert!(mean.abs() < 0.01);
+        assert!((variance - 1.).abs() < 0.02);
+        assert!((0..1000).all(|_| a.below(7) < 7));
+    }
+
+    #[test]
+    fn bootstrap() {
+        let dataset = Dataset::us_annual();
+        let generator = Bootstrap::new(&dataset, 5);
+        let sample = generator.generate(&mut Rng::new(1), 12);
+        assert_eq!(sample.stocks.len(), 12);
+        // The first block is five consecutive years of history.
+        let start = dataset
+            .stocks
+            .iter()
+            .position(|s| *s == sample.stocks[0])
+            .unwrap();
+        for i in 0..5 {
+            let index = (start + i) % dataset.len();
+            assert_eq!(sample.bonds[i], dataset.bonds[index]);
+            assert_eq!(sample.inflation[i], dataset.inflation[index]);
+        }
+
+        let backtest = Backtest::from_dataset(1000., vec![40.; 30], vec![0.; 30], &dataset);
+        let fractions = [0.6; 30];
+        let a = backtest.simulate(&generator, &fractions, 200, 7);
+        let b = backtest.simulate(&generator, &fractions, 200, 7);
+        assert_eq!(a.ending_values, b.ending_values);
+        assert!(a.success_rate() > 0.5 && a.success_rate() <= 1.);
+        assert!(a.percentile(0.1) <= a.percentile(0.5));
+        // A trial is just a backtest over the sampled returns.
+        let sample = generator.generate(&mut Rng::new(7), 30);
+        assert_eq!(
+            backtest
+                .with_sample(&sample)
+                .single_run(&fractions, 0, false),
+            a.ending_values[0]
+        );
+    }
+}
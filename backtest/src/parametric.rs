@@ -0,0 +1,258 @@
+use crate::dataset::Dataset;
+use crate::monte_carlo::{ReturnGenerator, Rng, Sample};
+
+// Made up returns from a model fitted to history, rather than history itself.
+// The log growth factors ln(1 + r / 100) of stocks, bonds and inflation are
+// jointly normal, so returns are lognormal and can't fall below -100%, with
+// the means and covariance of the dataset.  A Student-t variant has the same
+// means and covariance of the log growth factors but fatter tails: more
+// crashes and more booms.  The mean returns themselves aren't the same: the
+// exponential of a t has no finite mean, so see mean_returns.
+#[derive(Clone, Debug)]
+pub struct Lognormal {
+    periods_per_year: usize,
+    // Of the log growth factors, in the order stocks, bonds, inflation.
+    means: [f64; 3],
+    covariance: [[f64; 3]; 3],
+    cholesky: [[f64; 3]; 3],
+    degrees_of_freedom: Option<usize>, // Student-t when set.
+}
+
+impl Lognormal {
+    // An error if the dataset is too short, a series is constant, or the
+    // covariance is otherwise degenerate.
+    pub fn fit(dataset: &Dataset) -> Result<Self, String> {
+        if dataset.len() <= 3 {
+            return Err(format!(
+                "too few periods of data to fit a model to: {}",
+                dataset.len()
+            ));
+        }
+        let logs: Vec<Vec<f64>> = [&dataset.stocks, &dataset.bonds, &dataset.inflation]
+            .iter()
+            .map(|series| series.iter().map(|r| (1. + r / 100.).ln()).collect())
+            .collect();
+        let n = dataset.len() as f64;
+        let mut means = [0.; 3];
+        for (mean, logs) in means.iter_mut().zip(&logs) {
+            *mean = logs.iter().sum::<f64>() / n;
+        }
+        let mut covariance = [[0.; 3]; 3];
+        for a in 0..3 {
+            for b in 0..3 {
+                covariance[a][b] = logs[a]
+                    .iter()
+                    .zip(&logs[b])
+                    .map(|(x, y)| (x - means[a]) * (y - means[b]))
+                    .sum::<f64>()
+                    / (n - 1.);
+            }
+        }
+        // Rounding leaves a constant series a tiny variance rather than none.
+        for (i, name) in ["stocks", "bonds", "inflation"].iter().enumerate() {
+            if covariance[i][i] < 1e-12 {
+                return Err(format!("constant {}, so there's nothing to fit", name));
+            }
+        }
+        Ok(Lognormal {
+            periods_per_year: dataset.periods_per_year,
+            means,
+            covariance,
+            cholesky: cholesky(&covariance)?,
+            degrees_of_freedom: None,
+        })
+    }
+
+    // Student-t with the given degrees of freedom, scaled to keep the
+    // covariance.  Fewer degrees of freedom, fatter tails; 4 or 5 is about
+    // right for annual stock returns.
+    pub fn student_t(mut self, degrees_of_freedom: usize) -> Self {
+        assert!(degrees_of_freedom > 2);
+        self.degrees_of_freedom = Some(degrees_of_freedom);
+        self
+    }
+
+    // Expected returns per period in percent, [stocks, bonds, inflation], of
+    // the lognormal.  For the Student-t variant these are only approximate:
+    // its log growth factors have the same means and covariance, but its
+    // fatter tails push sample means of the returns higher, and by an amount
+    // that depends on the number of draws.
+    pub fn mean_returns(&self) -> [f64; 3] {
+        let mut returns = [0.; 3];
+        for (i, r) in returns.iter_mut().enumerate() {
+            *r = ((self.means[i] + self.covariance[i][i] / 2.).exp() - 1.) * 100.;
+        }
+        returns
+    }
+
+    // Replace the expected returns, keeping the volatilities and
+    // correlations, e.g. to assume a lower equity premium.  Approximate for
+    // the Student-t variant, as for mean_returns, though a shift such as the
+    // one below still lowers the t's returns by about as much:
+    //
+    //     let [stocks, bonds, inflation] = model.mean_returns();
+    //     model.with_mean_returns([stocks - 2., bonds, inflation])
+    pub fn with_mean_returns(mut self, returns: [f64; 3]) -> Self {
+        for (i, r) in returns.iter().enumerate() {
+            assert!(*r > -100.);
+            self.means[i] = (1. + r / 100.).ln() - self.covariance[i][i] / 2.;
+        }
+        self
+    }
+
+    // Correlation of two of stocks (0), bonds (1) and inflation (2).
+    pub fn correlation(&self, a: usize, b: usize) -> f64 {
+        self.covariance[a][b] / (self.covariance[a][a] * self.covariance[b][b]).sqrt()
+    }
+}
+
+impl ReturnGenerator for Lognormal {
+    fn periods_per_year(&self) -> usize {
+        self.periods_per_year
+    }
+
+    fn generate(&self, rng: &mut Rng, periods: usize) -> Sample {
+        let mut sample = Sample::default();
+        for _ in 0..periods {
+            let z = [rng.normal(), rng.normal(), rng.normal()];
+            // A t is a normal divided by the root of an independent
+            // chi-squared over its degrees of freedom.  The (v - 2) / v makes
+            // its variance 1.
+            let scale = match self.degrees_of_freedom {
+                Some(v) => {
+                    let chi_squared: f64 = (0..v).map(|_| rng.normal().powi(2)).sum();
+                    ((v as f64 - 2.) / chi_squared).sqrt()
+                }
+                None => 1.,
+            };
+            let mut x = [0.; 3];
+            for (i, x) in x.iter_mut().enumerate() {
+                let correlated: f64 = (0..=i).map(|j| self.cholesky[i][j] * z[j]).sum();
+                *x = ((self.means[i] + scale * correlated).exp() - 1.) * 100.;
+            }
+            sample.stocks.push(x[0]);
+            sample.bonds.push(x[1]);
+            sample.inflation.push(x[2]);
+        }
+        sample
+    }
+}
+
+// Lower triangular L with L Lᵀ = a, for a positive definite a.
+fn cholesky(a: &[[f64; 3]; 3]) -> Result<[[f64; 3]; 3], String> {
+    let mut l = [[0.; 3]; 3];
+    for i in 0..3 {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let d = a[i][i] - sum;
+                // NaN from returns of -100% or less.
+                if d.is_nan() || d <= 0. {
+                    return Err(
+                        "covariance of stocks, bonds and inflation isn't positive definite"
+                            .to_string(),
+                    );
+                }
+                l[i][j] = d.sqrt();
+            } else {
+                l[i][j] = (a[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    Ok(l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lognormal;
+    use crate::dataset::Dataset;
+    use crate::monte_carlo::{ReturnGenerator, Rng};
+    use crate::Backtest;
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    fn correlation(a: &[f64], b: &[f64]) -> f64 {
+        let (ma, mb) = (mean(a), mean(b));
+        let cov: f64 = a.iter().zip(b).map(|(x, y)| (x - ma) * (y - mb)).sum();
+        let va: f64 = a.iter().map(|x| (x - ma).powi(2)).sum();
+        let vb: f64 = b.iter().map(|y| (y - mb).powi(2)).sum();
+        cov / (va * vb).sqrt()
+    }
+
+    // Excess kurtosis: 0 for a normal, more for fat tails.
+    fn kurtosis(values: &[f64]) -> f64 {
+        let m = mean(values);
+        let variance = mean(&values.iter().map(|x| (x - m).powi(2)).collect::<Vec<_>>());
+        mean(&values.iter().map(|x| (x - m).powi(4)).collect::<Vec<_>>()) / variance.powi(2) - 3.
+    }
+
+    #[test]
+    fn lognormal() {
+        let dataset = Dataset::us_annual();
+        let model = Lognormal::fit(&dataset).unwrap();
+        let [stocks, bonds, inflation] = model.mean_returns();
+        // The model's means are close to history's, though not equal, since
+        // history isn't lognormal.
+        assert!((stocks - mean(&dataset.stocks)).abs() < 0.5);
+        assert!((bonds - mean(&dataset.bonds)).abs() < 0.5);
+        assert!((inflation - mean(&dataset.inflation)).abs() < 0.5);
+
+        let a = model.generate(&mut Rng::new(3), 200_000);
+        assert_eq!(
+            a.stocks[..10],
+            model.generate(&mut Rng::new(3), 10).stocks[..]
+        );
+        assert!((mean(&a.stocks) - stocks).abs() < 0.2);
+        assert!((mean(&a.inflation) - inflation).abs() < 0.1);
+        let log = |r: &[f64]| r.iter().map(|r| (1. + r / 100.).ln()).collect::<Vec<_>>();
+        assert!(
+            (correlation(&log(&a.stocks), &log(&a.bonds)) - model.correlation(0, 1)).abs() < 0.01
+        );
+        assert!(a.stocks.iter().all(|r| *r > -100.));
+
+        // Two points less equity premium, same correlations.
+        let lower = model
+            .clone()
+            .with_mean_returns([stocks - 2., bonds, inflation]);
+        assert!((lower.mean_returns()[0] - (stocks - 2.)).abs() < 1e-9);
+        let b = lower.generate(&mut Rng::new(3), 200_000);
+        assert!((mean(&b.stocks) - (stocks - 2.)).abs() < 0.2);
+        assert!((correlation(&b.stocks, &b.bonds) - correlation(&a.stocks, &a.bonds)).abs() < 0.01);
+
+        // Same covariance, fatter tails.
+        let t = model
+            .clone()
+            .student_t(5)
+            .generate(&mut Rng::new(3), 200_000);
+        assert!((mean(&t.stocks) - stocks).abs() < 0.3);
+        assert!(kurtosis(&log(&t.stocks)) > kurtosis(&log(&a.stocks)) + 1.);
+
+        let backtest = Backtest::from_dataset(1000., vec![40.; 30], vec![0.; 30], &dataset);
+        let fractions = [0.6; 30];
+        let normal = backtest.simulate(&model, &fractions, 2000, 1);
+        let pessimistic = backtest.simulate(&lower, &fractions, 2000, 1);
+        assert!(pessimistic.success_rate() < normal.success_rate());
+    }
+
+    #[test]
+    fn unfittable() {
+        let dataset = Dataset::us_annual();
+        let short = Dataset {
+            stocks: dataset.stocks[..3].to_vec(),
+            bonds: dataset.bonds[..3].to_vec(),
+            inflation: dataset.inflation[..3].to_vec(),
+            ..dataset.clone()
+        };
+        assert!(Lognormal::fit(&short).is_err());
+        let constant = Dataset {
+            inflation: vec![3.; dataset.len()],
+            ..dataset
+        };
+        assert_eq!(
+            Lognormal::fit(&constant).unwrap_err(),
+            "constant inflation, so there's nothing to fit"
+        );
+    }
+}
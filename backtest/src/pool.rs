@@ -0,0 +1,159 @@
+use crate::dataset::Dataset;
+use crate::{grid_search, Backtest, Range};
+
+// The same plan backtested over several market histories, with every window
+// of every history counted.  Looking only at the US, the big winner of the
+// last 150 years, gives results with a lot of survivorship bias.
+pub struct Pool {
+    backtests: Vec<(String, Backtest)>,
+}
+
+impl Pool {
+    // Datasets too short for a single window are left out.
+    pub fn new<'a, I: IntoIterator<Item = &'a Dataset>>(
+        start_portfolio: f64,
+        real_expenses: Vec<f64>,
+        nominal_expenses: Vec<f64>,
+        datasets: I,
+    ) -> Self {
+        let backtests = datasets
+            .into_iter()
+            .filter(|dataset| dataset.len() >= real_expenses.len())
+            .map(|dataset| {
+                let backtest = Backtest::from_dataset(
+                    start_portfolio,
+                    real_expenses.clone(),
+                    nominal_expenses.clone(),
+                    dataset,
+                );
+                (dataset.name.clone(), backtest)
+            })
+            .collect();
+        Self::from_backtests(backtests)
+    }
+
+    // For backtests that need more set up than Pool::new gives, such as
+    // leverage or annuities.
+    pub fn from_backtests(backtests: Vec<(String, Backtest)>) -> Self {
+        assert!(!backtests.is_empty());
+        Pool { backtests }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.backtests
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    pub fn backtest(&self, name: &str) -> Option<&Backtest> {
+        self.backtests
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, backtest)| backtest)
+    }
+
+    // Like Backtest::worst_year, across all datasets.  Years come with the
+    // name of their dataset.
+    #[allow(clippy::type_complexity)]
+    pub fn worst_year(&self, stock_fractions: &[f64]) -> ((&str, usize), f64, (&str, usize), f64) {
+        let mut worst = (("", usize::MAX), f64::INFINITY);
+        let mut second_worst = (("", usize::MAX), f64::INFINITY);
+        for (name, backtest) in &self.backtests {
+            let (year, value, second_year, second_value) = backtest.worst_year(stock_fractions);
+            for (year, value) in [(year, value), (second_year, second_value)] {
+                if value < worst.1 {
+                    second_worst = worst;
+                    worst = ((name.as_str(), year), value);
+                } else if value < second_worst.1 {
+                    second_worst = ((name.as_str(), year), value);
+                }
+            }
+        }
+        (worst.0, worst.1, second_worst.0, second_worst.1)
+    }
+
+    // Fraction of all windows, across all datasets, that end with money left.
+    pub fn success_rate(&self, stock_fractions: &[f64]) -> f64 {
+        let mut windows = 0;
+        let mut successes = 0;
+        for (_, backtest) in &self.backtests {
+            for (_, value) in backtest.ending_values(stock_fractions) {
+                windows += 1;
+                successes += (value >= 0.) as usize;
+            }
+        }
+        successes as f64 / windows as f64
+    }
+
+    // Success rate of each dataset on its own.
+    pub fn success_rates(&self, stock_fractions: &[f64]) -> Vec<(&str, f64)> {
+        self.backtests
+            .iter()
+            .map(|(name, backtest)| (name.as_str(), backtest.success_rate(stock_fractions)))
+            .collect()
+    }
+
+    // Like Backtest::best_fractions, maximizing the worst ending value over
+    // every window of every dataset.
+    pub fn best_fractions(
+        &self,
+        ranges: &[Range],
+        get_fractions: fn(&[f64], usize) -> Vec<f64>,
+        length: usize,
+    ) -> (Vec<f64>, f64, (&str, usize)) {
+        grid_search(ranges, |values| {
+            let (worst, value, _, _) = self.worst_year(&get_fractions(values, length));
+            (value, worst)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pool;
+    use crate::dataset::{Dataset, Datasets};
+
+    #[test]
+    fn pooled_worst_year() {
+        // A made up country whose stocks lose a little every year.
+        let mut datasets = Datasets::new();
+        let csv: String = (1950..2000)
+            .map(|year| format!("{},-1,0.5,3\n", year))
+            .collect();
+        datasets.load_annual_csv("losing", &csv).unwrap();
+        datasets.add(Dataset {
+            name: "too short".to_string(),
+            ..datasets.get("losing").unwrap().clone()
+        });
+        let mut short = datasets.get("too short").unwrap().clone();
+        short.stocks.truncate(20);
+        short.bonds.truncate(20);
+        short.inflation.truncate(20);
+        datasets.add(short);
+        assert_eq!(datasets.get("losing").unwrap().first_year, 1950);
+
+        let pool = Pool::new(1000., vec![40.; 30], vec![0.; 30], datasets.iter());
+        assert_eq!(pool.names(), vec!["losing", "us"]);
+
+        let fractions = vec![1.; 30];
+        let (worst, worst_value, second, second_value) = pool.worst_year(&fractions);
+        let mut expected = Vec::new();
+        for name in ["losing", "us"] {
+            let (year, value, second_year, second_value) =
+                pool.backtest(name).unwrap().worst_year(&fractions);
+            expected.push(((name, year), value));
+            expected.push(((name, second_year), second_value));
+        }
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+        assert_eq!((worst, worst_value), expected[0]);
+        assert_eq!((second, second_value), expected[1]);
+
+        let us = pool.backtest("us").unwrap();
+        let us_windows = us.ending_values(&fractions).len() as f64;
+        let rates = pool.success_rates(&fractions);
+        assert_eq!(rates[0], ("losing", 0.));
+        let pooled = rates[1].1 * us_windows / (us_windows + 21.);
+        assert!((pool.success_rate(&fractions) - pooled).abs() < 1e-12);
+    }
+}
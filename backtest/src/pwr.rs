@@ -0,0 +1,276 @@
+use crate::svg;
+use crate::Backtest;
+
+// The perfect withdrawal rate of a window is the constant real spending that
+// leaves exactly the legacy at the end.  The portfolio at the end of each year
+// is (portfolio - spending) * growth, so the ending value is an affine
+// function of spending, a - b * spending, and two runs are enough to find it.
+//
+// The spending replaces real_expenses.  Nominal expenses, events and
+// annuities still apply, since they don't depend on spending.  The one
+// exception is an annuity bought with a fraction of a portfolio that has
+// already gone negative, which is never the case at the perfect rate.
+//
+// Leverage keeps the ending value affine, since whether there's a margin call
+// depends only on the period's returns, not on the size of the portfolio.  A
+// leverage model where it did would break that, so with leverage the answer
+// is checked with a third run.
+impl Backtest {
+    // Perfect real spending per period for the window at year_offset.  The
+    // window is as long as stock_fractions, which can be shorter than
+    // real_expenses to ask about a shorter retirement.
+    pub fn perfect_withdrawal(
+        &self,
+        stock_fractions: &[f64],
+        year_offset: usize,
+        legacy: f64,
+    ) -> f64 {
+        let end = |spending: f64| {
+            let spending = vec![spending; stock_fractions.len()];
+            self.run(&spending, stock_fractions, year_offset, false, None)
+        };
+        let a = end(0.);
+        let b = a - end(1.);
+        let spending = (a - legacy) / b;
+        if self.leverage.is_some() {
+            let error = end(spending) - legacy;
+            assert!(
+                error.abs() <= 1e-6 * self.start_portfolio.max(legacy.abs()),
+                "ending value isn't affine in spending"
+            );
+        }
+        spending
+    }
+
+    // The perfect withdrawal rate for each start year: annual spending as a
+    // fraction of the starting portfolio.
+    pub fn perfect_withdrawal_rates(
+        &self,
+        stock_fractions: &[f64],
+        legacy: f64,
+    ) -> Vec<(usize, f64)> {
+        self.window_offsets(stock_fractions.len())
+            .into_iter()
+            .map(|year_offset| {
+                let spending = self.perfect_withdrawal(stock_fractions, year_offset, legacy);
+                (
+                    self.year_of(year_offset),
+                    spending * self.periods_per_year as f64 / self.start_portfolio,
+                )
+            })
+            .collect()
+    }
+
+    // The safe withdrawal rate for every start year and retirement length, at
+    // a constant stock fraction: the perfect withdrawal rate with nothing
+    // left over.  Horizons are in years, and no longer than real_expenses.
+    pub fn swr_table(&self, stock_fraction: f64, horizons: &[usize]) -> SwrTable {
+        let periods: Vec<usize> = horizons
+            .iter()
+            .map(|years| years * self.periods_per_year)
+            .collect();
+        assert!(periods.iter().all(|&p| p <= self.real_expenses.len()));
+        let shortest = *periods.iter().min().unwrap();
+        // One row per calendar year, starting in January with monthly data.
+        let starts: Vec<usize> = self
+            .window_offsets(shortest)
+            .into_iter()
+            .filter(|&offset| self.date_of(offset).1 == 1)
+            .collect();
+        let rates = periods
+            .iter()
+            .map(|&length| {
+                starts
+                    .iter()
+                    .map(|&year_offset| {
+                        self.window_fits(year_offset, length).then(|| {
+                            let fractions = vec![stock_fraction; length];
+                            self.perfect_withdrawal(&fractions, year_offset, 0.)
+                                * self.periods_per_year as f64
+                                / self.start_portfolio
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        SwrTable {
+            years: starts.iter().map(|&offset| self.year_of(offset)).collect(),
+            horizons: horizons.to_vec(),
+            rates,
+        }
+    }
+}
+
+// Safe withdrawal rates by start year and horizon, from Backtest::swr_table.
+#[derive(Clone, Debug)]
+pub struct SwrTable {
+    pub years: Vec<usize>,
+    pub horizons: Vec<usize>,
+    // rates[h][y] is for horizons[h] starting in years[y], or None if the
+    // data ends before the window does.
+    pub rates: Vec<Vec<Option<f64>>>,
+}
+
+impl SwrTable {
+    // One row per start year, one column per horizon, rates in percent.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("year");
+        for horizon in &self.horizons {
+            csv += &format!(",{}", horizon);
+        }
+        csv += "\n";
+        for (y, year) in self.years.iter().enumerate() {
+            csv += &year.to_string();
+            for rates in &self.rates {
+                match rates[y] {
+                    Some(rate) => csv += &format!(",{:.3}", rate * 100.),
+                    None => csv += ",",
+                }
+            }
+            csv += "\n";
+        }
+        csv
+    }
+
+    pub fn to_svg(&self, title: &str) -> String {
+        let percent: Vec<Vec<Option<f64>>> = self
+            .rates
+            .iter()
+            .map(|rates| rates.iter().map(|r| r.map(|r| r * 100.)).collect())
+            .collect();
+        svg::heat_map(
+            title,
+            &self.years,
+            &self
+                .horizons
+                .iter()
+                .map(|h| format!("{} years", h))
+                .collect::<Vec<_>>(),
+            &percent,
+            "%",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataset::Dataset;
+    use crate::events::{Dollars, Event};
+    use crate::leverage::{BorrowRate, Leverage};
+    use crate::time_series;
+    use crate::Backtest;
+
+    #[test]
+    fn swr_table() {
+        let backtest = Backtest::new(
+            1000.,
+            vec![0.; 40],
+            vec![0.; 40],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        );
+        let table = backtest.swr_table(0.6, &[20, 30]);
+        assert_eq!(table.years.len(), time_series::YEARS - 20 + 1);
+        assert_eq!(table.years[0], 1871);
+        assert!(table.rates[0].iter().all(|r| r.is_some()));
+        assert_eq!(table.rates[1].iter().filter(|r| r.is_none()).count(), 10);
+
+        // The 30 year rates are the perfect withdrawal rates for 30 years.
+        let rates = backtest.perfect_withdrawal_rates(&[0.6; 30], 0.);
+        assert_eq!(table.rates[1][50], Some(rates[50].1));
+        // Longer retirements need lower rates, on the whole.
+        let mean = |rates: &[Option<f64>]| {
+            let rates: Vec<f64> = rates.iter().flatten().copied().collect();
+            rates.iter().sum::<f64>() / rates.len() as f64
+        };
+        assert!(mean(&table.rates[1]) < mean(&table.rates[0]));
+
+        let csv = table.to_csv();
+        assert!(csv.starts_with("year,20,30\n1871,"));
+        assert!(csv.ends_with(",\n"));
+        let svg = table.to_svg("Safe withdrawal rate");
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn swr_table_starts_in_january() {
+        // Monthly data from July 1871, so the first January is 1872.
+        let monthly = Dataset::us_annual().spread_over_months();
+        let from_july = Dataset {
+            first_month: 7,
+            stocks: monthly.stocks[6..].to_vec(),
+            bonds: monthly.bonds[6..].to_vec(),
+            inflation: monthly.inflation[6..].to_vec(),
+            assets: Default::default(),
+            series: Default::default(),
+            ..monthly
+        };
+        let backtest =
+            Backtest::from_dataset(1000., vec![0.; 10 * 12], vec![0.; 10 * 12], &from_july);
+        let table = backtest.swr_table(0.6, &[10]);
+        assert_eq!(table.years[..3], [1872, 1873, 1874]);
+        assert_eq!(*table.years.last().unwrap(), 2011);
+        let rate = backtest.perfect_withdrawal(&[0.6; 10 * 12], 6, 0.) * 12. / 1000.;
+        assert_eq!(table.rates[0][0], Some(rate));
+    }
+
+    #[test]
+    fn perfect_withdrawal_hits_legacy() {
+        let backtest = |real_expenses: Vec<f64>| {
+            Backtest::new(
+                1000.,
+                real_expenses,
+                vec![5.; 30],
+                time_series::TOTAL_STOCK_MARKET.to_vec(),
+                time_series::TOTAL_BOND_MARKET.to_vec(),
+            )
+        };
+        let fractions: Vec<f64> = (0..30).map(|i| 0.4 + i as f64 / 60.).collect();
+
+        let rates = backtest(vec![0.; 30]).perfect_withdrawal_rates(&fractions, 100.);
+        assert_eq!(rates.len(), time_series::YEARS - 30 + 1);
+        assert_eq!(rates[0].0, 1871);
+        for (year, rate) in [rates[58], rates[95], rates[120]] {
+            let spending = backtest(vec![rate * 1000.; 30]);
+            let end = spending.single_run(&fractions, year - time_series::FIRST_YEAR, false);
+            assert!((end - 100.).abs() < 1e-6);
+        }
+
+        // 1966 is famously bad; 1982 famously good.
+        assert!(rates[1966 - 1871].1 < rates[1982 - 1871].1);
+
+        // Events are paid on top of the perfect spending.
+        let roof = vec![Event::expense("roof", 5, 50., Dollars::Real)];
+        let year = 1966 - time_series::FIRST_YEAR;
+        let with_roof = backtest(vec![0.; 30]).with_events(roof.clone());
+        let spending = with_roof.perfect_withdrawal(&fractions, year, 100.);
+        assert!(spending < backtest(vec![0.; 30]).perfect_withdrawal(&fractions, year, 100.));
+        let end = backtest(vec![spending; 30])
+            .with_events(roof)
+            .single_run(&fractions, year, false);
+        assert!((end - 100.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn perfect_withdrawal_with_margin_calls() {
+        let backtest = |real_expenses: Vec<f64>| {
+            Backtest::new(
+                1000.,
+                real_expenses,
+                vec![0.; 10],
+                time_series::TOTAL_STOCK_MARKET.to_vec(),
+                time_series::TOTAL_BOND_MARKET.to_vec(),
+            )
+            .with_leverage(Leverage::new(
+                BorrowRate::over_short_term_treasuries(2.),
+                2.,
+                0.4,
+            ))
+        };
+        // 1929 - 1938 has margin calls at 2:1.
+        let year = 1929 - time_series::FIRST_YEAR;
+        let spending = backtest(vec![0.; 10]).perfect_withdrawal(&[2.; 10], year, 0.);
+        let end = backtest(vec![spending; 10]).single_run(&[2.; 10], year, false);
+        assert!(end.abs() < 1e-6);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::dataset::Dataset;
+use crate::monte_carlo::{ReturnGenerator, Rng, Sample};
+use crate::parametric::Lognormal;
+
+// A Markov regime-switching model.  Each year of history is labelled normal,
+// high inflation or crash, and the model keeps the chance of moving from each
+// regime to each other one, along with a lognormal fitted to the years of
+// each regime.  A made up history is a walk through the regimes with a draw
+// from the current regime's distribution each year, so bad stretches such as
+// 1966 - 1981 come in runs, as they did, rather than one year at a time.
+// Only annual datasets are supported.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Regime {
+    Normal,
+    HighInflation,
+    Crash,
+}
+
+pub const REGIMES: [Regime; 3] = [Regime::Normal, Regime::HighInflation, Regime::Crash];
+
+impl Regime {
+    // A crash is a year of real stock returns below crash_below, in percent,
+    // and comes before high inflation, which is above inflation_above.
+    pub fn classify(stocks: f64, inflation: f64, crash_below: f64, inflation_above: f64) -> Self {
+        if stocks < crash_below {
+            Regime::Crash
+        } else if inflation > inflation_above {
+            Regime::HighInflation
+        } else {
+            Regime::Normal
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RegimeSwitching {
+    // transitions[a][b] is the chance that a year in regime a is followed by
+    // one in regime b.
+    pub transitions: [[f64; 3]; 3],
+    // Fraction of history spent in each regime, where walks start.
+    pub frequencies: [f64; 3],
+    models: Vec<Lognormal>,
+}
+
+impl RegimeSwitching {
+    // Fit to history with the given thresholds, in percent; -15 and 6 split
+    // the US data into 12 crash years and 19 of high inflation.  Every regime
+    // needs a few years to fit a distribution to, or it's an error.
+    pub fn fit(dataset: &Dataset, crash_below: f64, inflation_above: f64) -> Result<Self, String> {
+        if dataset.periods_per_year != 1 {
+            return Err("regimes need annual data".to_string());
+        }
+        let labels = regimes(dataset, crash_below, inflation_above);
+
+        let mut counts = [[0.; 3]; 3];
+        for pair in labels.windows(2) {
+            counts[pair[0].index()][pair[1].index()] += 1.;
+        }
+        let mut transitions = [[0.; 3]; 3];
+        for (row, counts) in transitions.iter_mut().zip(&counts) {
+            let total: f64 = counts.iter().sum();
+            for (p, count) in row.iter_mut().zip(counts) {
+                // A regime only seen in the last year goes anywhere.
+                *p = if total > 0. { count / total } else { 1. / 3. };
+            }
+        }
+
+        let mut frequencies = [0.; 3];
+        for label in &labels {
+            frequencies[label.index()] += 1. / labels.len() as f64;
+        }
+
+        let models = REGIMES
+            .iter()
+            .map(|regime| {
+                let years: Vec<usize> = (0..dataset.len())
+                    .filter(|i| labels[*i] == *regime)
+                    .collect();
+                if years.len() <= 3 {
+                    return Err(format!(
+                        "too few {:?} years to fit: {}",
+                        regime,
+                        years.len()
+                    ));
+                }
+                let pick = |series: &[f64]| years.iter().map(|i| series[*i]).collect();
+                Lognormal::fit(&Dataset {
+                    stocks: pick(&dataset.stocks),
+                    bonds: pick(&dataset.bonds),
+                    inflation: pick(&dataset.inflation),
+                    ..dataset.clone()
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(RegimeSwitching {
+            transitions,
+            frequencies,
+            models,
+        })
+    }
+
+    // The expected number of years a regime lasts once it starts.
+    pub fn expected_length(&self, regime: Regime) -> f64 {
+        1. / (1. - self.transitions[regime.index()][regime.index()])
+    }
+}
+
+// The regime of each year of a dataset.
+pub fn regimes(dataset: &Dataset, crash_below: f64, inflation_above: f64) -> Vec<Regime> {
+    dataset
+        .stocks
+        .iter()
+        .zip(&dataset.inflation)
+        .map(|(s, i)| Regime::classify(*s, *i, crash_below, inflation_above))
+        .collect()
+}
+
+// Index drawn with the given probabilities.
+fn choose(rng: &mut Rng, probabilities: &[f64; 3]) -> usize {
+    let mut u = rng.uniform();
+    for (i, p) in probabilities.iter().enumerate() {
+        if u < *p {
+            return i;
+        }
+        u -= p;
+    }
+    probabilities.len() - 1
+}
+
+impl ReturnGenerator for RegimeSwitching {
+    fn periods_per_year(&self) -> usize {
+        1
+    }
+
+    fn generate(&self, rng: &mut Rng, periods: usize) -> Sample {
+        let mut sample = Sample::default();
+        let mut regime = choose(rng, &self.frequencies);
+        for _ in 0..periods {
+            let year = self.models[regime].generate(rng, 1);
+            sample.stocks.extend(year.stocks);
+            sample.bonds.extend(year.bonds);
+            sample.inflation.extend(year.inflation);
+            regime = choose(rng, &self.transitions[regime]);
+        }
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{regimes, Regime, RegimeSwitching};
+    use crate::dataset::Dataset;
+    use crate::monte_carlo::{ReturnGenerator, Rng};
+    use crate::parametric::Lognormal;
+    use crate::Backtest;
+
+    // Correlation of each value with the next.
+    fn autocorrelation(values: &[f64]) -> f64 {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance: f64 = values.iter().map(|x| (x - mean).powi(2)).sum();
+        let covariance: f64 = values
+            .windows(2)
+            .map(|w| (w[0] - mean) * (w[1] - mean))
+            .sum();
+        covariance / variance
+    }
+
+    #[test]
+    fn regime_switching() {
+        let dataset = Dataset::us_annual();
+        let labels = regimes(&dataset, -15., 6.);
+        assert_eq!(labels[1931 - 1871], Regime::Crash);
+        assert_eq!(labels[1979 - 1871], Regime::HighInflation);
+        assert_eq!(labels.iter().filter(|r| **r == Regime::Crash).count(), 12);
+
+        let model = RegimeSwitching::fit(&dataset, -15., 6.).unwrap();
+        for row in &model.transitions {
+            assert!((row.iter().sum::<f64>() - 1.).abs() < 1e-12);
+        }
+        assert!((model.frequencies.iter().sum::<f64>() - 1.).abs() < 1e-12);
+        // High inflation is persistent: more likely after a year of it than
+        // in general.
+        let high = Regime::HighInflation as usize;
+        assert!(model.transitions[high][high] > model.frequencies[high]);
+        assert!(model.expected_length(Regime::HighInflation) > 1.);
+
+        // So inflation runs in streaks, which i.i.d. draws don't have.
+        let sample = model.generate(&mut Rng::new(5), 50_000);
+        let iid = Lognormal::fit(&dataset)
+            .unwrap()
+            .generate(&mut Rng::new(5), 50_000);
+        assert!(autocorrelation(&sample.inflation) > 0.04);
+        assert!(autocorrelation(&iid.inflation).abs() < 0.05);
+
+        let backtest = Backtest::from_dataset(1000., vec![40.; 30], vec![0.; 30], &dataset);
+        let a = backtest.simulate(&model, &[0.6; 30], 500, 9);
+        let b = backtest.simulate(&model, &[0.6; 30], 500, 9);
+        assert_eq!(a.ending_values, b.ending_values);
+        assert!(a.success_rate() > 0.5);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::dataset::Dataset;
+use crate::svg::escape;
+use crate::{percentile, Backtest};
+use std::fmt::Write;
+use std::io;
+
+// A single self-contained HTML page describing a plan: what went in, which
+// data it was run on, how it did, and charts.  Nothing is linked, so the
+// file can be mailed or attached to a forum post as is.
+pub struct Report<'a> {
+    title: String,
+    dataset: &'a Dataset,
+    backtest: &'a Backtest,
+    stock_fractions: Vec<f64>,
+    others: Vec<(String, Vec<f64>)>,
+}
+
+impl<'a> Report<'a> {
+    // `backtest` must have been made from `dataset`.
+    pub fn new(
+        title: &str,
+        dataset: &'a Dataset,
+        backtest: &'a Backtest,
+        stock_fractions: &[f64],
+    ) -> Self {
+        assert_eq!(dataset.len(), backtest.bond_return.len());
+        Report {
+            title: title.to_string(),
+            dataset,
+            backtest,
+            stock_fractions: stock_fractions.to_vec(),
+            others: Vec::new(),
+        }
+    }
+
+    // Another glide path to show alongside the plan's.
+    pub fn compare(mut self, label: &str, stock_fractions: &[f64]) -> Self {
+        self.others
+            .push((label.to_string(), stock_fractions.to_vec()));
+        self
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        std::fs::write(path, self.html())
+    }
+
+    pub fn html(&self) -> String {
+        let mut html = String::new();
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\nbody {{ font-family: sans-serif; max-width: 900px; margin: auto; }}\n\
+             table {{ border-collapse: collapse; }}\n\
+             td, th {{ border: 1px solid #ccc; padding: 2px 8px; text-align: right; }}\n\
+             </style>\n</head>\n<body>\n<h1>{}</h1>",
+            escape(&self.title),
+            escape(&self.title)
+        )
+        .unwrap();
+        self.inputs(&mut html);
+        self.data(&mut html);
+        self.results(&mut html);
+        self.charts(&mut html);
+        html += "</body>\n</html>\n";
+        html
+    }
+
+    fn inputs(&self, html: &mut String) {
+        let b = self.backtest;
+        let years = b.real_expenses.len() as f64 / b.periods_per_year as f64;
+        let first_year = |expenses: &[f64]| expenses.iter().take(b.periods_per_year).sum::<f64>();
+        let mut rows = vec![
+            ("Starting portfolio".to_string(), dollars(b.start_portfolio)),
+            ("Retirement length".to_string(), format!("{} years", years)),
+            (
+                "Real expenses, first year".to_string(),
+                dollars(first_year(&b.real_expenses)),
+            ),
+            (
+                "Nominal expenses, first year".to_string(),
+                dollars(first_year(&b.nominal_expenses)),
+            ),
+            (
+                "Initial withdrawal rate".to_string(),
+                format!(
+                    "{:.2}%",
+                    (first_year(&b.real_expenses) + first_year(&b.nominal_expenses))
+                        / b.start_portfolio
+                        * 100.
+                ),
+            ),
+        ];
+        if let Some(leverage) = &b.leverage {
+            rows.push((
+                "Leverage".to_string(),
+                format!(
+                    "up to {}x, maintenance {}%",
+                    leverage.max_ratio,
+                    leverage.maintenance * 100.
+                ),
+            ));
+        }
+        for annuity in &b.annuities {
+            rows.push((
+                "Annuity".to_string(),
+                format!(
+                    "{:.0}% of the portfolio in year {}",
+                    annuity.fraction * 100.,
+                    annuity.purchase_year
+                ),
+            ));
+        }
+        for event in &b.events {
+            rows.push((
+                escape(&event.label),
+                format!("{} in year {}", dollars(event.amount), event.year),
+            ));
+        }
+        html.push_str("<h2>Inputs</h2>\n");
+        table(html, &["", ""], &rows_of(rows));
+
+        html.push_str("<p>Stock fraction by year: ");
+        let fractions: Vec<String> = self
+            .stock_fractions
+            .iter()
+            .step_by(b.periods_per_year)
+            .map(|f| format!("{:.0}%", f * 100.))
+            .collect();
+        writeln!(html, "{}</p>", fractions.join(" ")).unwrap();
+    }
+
+    fn data(&self, html: &mut String) {
+        let d = self.dataset;
+        let (last_year, last_month) = d.date(d.len() - 1);
+        let extra: Vec<&str> = d
+            .assets
+            .keys()
+            .chain(d.series.keys())
+            .map(|k| k.as_str())
+            .collect();
+        let rows = vec![
+            ("Dataset".to_string(), escape(&d.name)),
+            ("Source".to_string(), escape(&d.source)),
+            (
+                "Periods".to_string(),
+                format!(
+                    "{} {}, {}-{:02} to {}-{:02}",
+                    d.len(),
+                    if d.periods_per_year == 1 {
+                        "years"
+                    } else {
+                        "months"
+                    },
+                    d.first_year,
+                    d.first_month,
+                    last_year,
+                    last_month
+                ),
+            ),
+            ("Other series".to_string(), escape(&extra.join(", "))),
+        ];
+        html.push_str("<h2>Data</h2>\n");
+        table(html, &["", ""], &rows_of(rows));
+    }
+
+    fn results(&self, html: &mut String) {
+        let b = self.backtest;
+        html.push_str("<h2>Results</h2>\n");
+        let mut rows = Vec::new();
+        for (label, fractions) in self.strategies() {
+            let stats = b.window_stats(fractions);
+            let rates: Vec<f64> = b
+                .perfect_withdrawal_rates(fractions, 0.)
+                .into_iter()
+                .map(|(_, rate)| rate)
+                .collect();
+            rows.push(vec![
+                escape(label),
+                stats.windows.to_string(),
+                format!("{:.1}%", stats.success_rate * 100.),
+                format!("{:.2}%", percentile(&rates, 0.) * 100.),
+                format!("{:.2}%", percentile(&rates, 0.1) * 100.),
+                format!("{:.2}%", percentile(&rates, 0.5) * 100.),
+            ]);
+        }
+        table(
+            html,
+            &[
+                "Glide path",
+                "Windows",
+                "Success rate",
+                "Safe withdrawal rate",
+                "10th percentile",
+                "Median",
+            ],
+            &rows,
+        );
+        html.push_str(
+            "<p>Withdrawal rates are perfect withdrawal rates: the constant real spending, \
+             as a fraction of the starting portfolio, that runs the portfolio down to \
+             exactly zero.  The safe withdrawal rate is the lowest of them.</p>\n",
+        );
+
+        html.push_str("<h3>Worst windows</h3>\n");
+        let mut values = b.ending_values(&self.stock_fractions);
+        values.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let rows: Vec<Vec<String>> = values
+            .iter()
+            .take(5)
+            .map(|&(offset, value)| {
+                let drawdown = b.drawdown(&self.stock_fractions, offset);
+                vec![
+                    b.label(offset),
+                    dollars(value),
+                    format!("{:.1}%", drawdown.max_drawdown * 100.),
+                    dollars(drawdown.min_balance),
+                    format!(
+                        "{:.2}%",
+                        b.perfect_withdrawal(&self.stock_fractions, offset, 0.)
+                            * b.periods_per_year as f64
+                            / b.start_portfolio
+                            * 100.
+                    ),
+                ]
+            })
+            .collect();
+        table(
+            html,
+            &[
+                "Start",
+                "Ending value",
+                "Max drawdown",
+                "Lowest balance",
+                "Perfect withdrawal rate",
+            ],
+            &rows,
+        );
+
+        let attribution = b.worst_attribution(&self.stock_fractions);
+        write!(
+            html,
+            "<h3>Why {} was worst</h3>\n<p>With every year at the window's average returns \
+             and inflation ({:.1}% stocks, {:.1}% bonds, {:.1}% inflation), it would have \
+             ended with {} rather than {}.  These years did the most damage: each is how \
+             much more the plan would have ended with had that year been average.</p>\n",
+            attribution.start_year,
+            attribution.average_stocks,
+            attribution.average_bonds,
+            attribution.average_inflation,
+            dollars(attribution.average_value),
+            dollars(attribution.ending_value)
+        )
+        .unwrap();
+        let rows: Vec<Vec<String>> = attribution
+            .most_damaging(5)
+            .iter()
+            .map(|c| {
+                vec![
+                    c.year.to_string(),
+                    dollars(-c.total),
+                    dollars(-c.stocks),
+                    dollars(-c.bonds),
+                    dollars(-c.inflation),
+                ]
+            })
+            .collect();
+        table(
+            html,
+            &[
+                "Year",
+                "Cost",
+                "From stocks",
+                "From bonds",
+                "From inflation",
+            ],
+            &rows,
+        );
+    }
+
+    fn charts(&self, html: &mut String) {
+        let b = self.backtest;
+        html.push_str("<h2>Charts</h2>\n");
+
+        let mut values = b.ending_values(&self.stock_fractions);
+        values.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let offsets = [values[0].0, values[values.len() / 2].0];
+        html.push_str(&b.path_chart("Worst and median windows", &self.stock_fractions, &offsets));
+
+        let strategies: Vec<(&str, &[f64])> = self.strategies().collect();
+        html.push_str(&b.ending_values_chart("Ending value by start year", &strategies));
+        html.push_str(&b.glide_path_chart("Glide path", &self.stock_fractions));
+
+        // Safe withdrawal rates by horizon, at the plan's average allocation.
+        let average = self.stock_fractions.iter().sum::<f64>() / self.stock_fractions.len() as f64;
+        let horizons: Vec<usize> = [10, 20, 30, 40, 50, 60]
+            .iter()
+            .copied()
+            .filter(|&years| years * b.periods_per_year <= b.real_expenses.len())
+            .collect();
+        if !horizons.is_empty() {
+            let swr = b.swr_table(average, &horizons);
+            html.push_str(&swr.to_svg(&format!(
+                "Safe withdrawal rate at a constant {:.0}% stocks",
+                average * 100.
+            )));
+        }
+    }
+
+    fn strategies(&self) -> impl Iterator<Item = (&str, &[f64])> {
+        std::iter::once(("Plan", self.stock_fractions.as_slice())).chain(
+            self.others
+                .iter()
+                .map(|(label, fractions)| (label.as_str(), fractions.as_slice())),
+        )
+    }
+}
+
+fn dollars(value: f64) -> String {
+    format!("${:.0}k", value / 1e3)
+}
+
+fn rows_of(rows: Vec<(String, String)>) -> Vec<Vec<String>> {
+    rows.into_iter().map(|(k, v)| vec![k, v]).collect()
+}
+
+// Cells are HTML already.
+fn table(html: &mut String, header: &[&str], rows: &[Vec<String>]) {
+    html.push_str("<table>\n");
+    if header.iter().any(|h| !h.is_empty()) {
+        html.push_str("<tr>");
+        for h in header {
+            write!(html, "<th>{}</th>", escape(h)).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            write!(html, "<td>{}</td>", cell).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use crate::dataset::Dataset;
+    use crate::events::{Dollars, Event};
+    use crate::Backtest;
+
+    #[test]
+    fn report() {
+        let dataset = Dataset::us_annual();
+        let backtest =
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], &dataset)
+                .with_events(vec![Event::expense(
+                    "roof & gutters",
+                    5,
+                    20_000.,
+                    Dollars::Real,
+                )]);
+        let rising: Vec<f64> = (0..30).map(|i| 0.3 + i as f64 / 50.).collect();
+        let html = Report::new("4% <rule>", &dataset, &backtest, &[0.6; 30])
+            .compare("Rising equity", &rising)
+            .html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>4% &lt;rule&gt;</h1>"));
+        assert!(html.contains("Simba's backtesting spreadsheet"));
+        assert!(html.contains("<td>roof &amp; gutters</td>"));
+        assert!(html.contains("<td>Rising equity</td>"));
+        assert_eq!(html.matches("<svg").count(), 4);
+        assert!(html.contains("<h3>Why 1966 was worst</h3>"));
+        // Every chart is inline; nothing refers to another file.
+        assert!(!html.contains("src="));
+        assert!(!html.contains("href="));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::annuity::Purchased;
+use crate::Backtest;
+
+// Required minimum distributions from a tax-deferred account such as a
+// traditional IRA or 401(k).  Each year the account must pay out at least its
+// balance divided by the distribution period for your age, taxable or not.
+
+// IRS Uniform Lifetime Table, in effect from 2022: distribution periods for
+// ages 72 through 120.
+const UNIFORM_LIFETIME_START: usize = 72;
+const UNIFORM_LIFETIME: [f64; 49] = [
+    27.4, 26.5, 25.5, 24.6, 23.7, 22.9, 22.0, 21.1, 20.2, 19.4, 18.5, 17.7, 16.8, 16.0, 15.2, 14.4,
+    13.7, 12.9, 12.2, 11.5, 10.8, 10.1, 9.5, 8.9, 8.4, 7.8, 7.3, 6.8, 6.4, 6.0, 5.6, 5.2, 4.9, 4.6,
+    4.3, 4.1, 3.9, 3.7, 3.5, 3.4, 3.3, 3.1, 3.0, 2.9, 2.8, 2.7, 2.5, 2.3, 2.0,
+];
+
+// The Uniform Lifetime Table's distribution period, or None below age 72.
+pub fn distribution_period(age: usize) -> Option<f64> {
+    if age < UNIFORM_LIFETIME_START {
+        return None;
+    }
+    let i = (age - UNIFORM_LIFETIME_START).min(UNIFORM_LIFETIME.len() - 1);
+    Some(UNIFORM_LIFETIME[i])
+}
+
+// Splits the starting portfolio into taxable and tax-deferred accounts, both
+// invested with the same stock fraction.  Expenses come from taxable first.
+// Withdrawals from tax-deferred are taxed at a flat rate, and whatever an RMD
+// forces out beyond what's needed is reinvested in taxable after tax.  Taxes
+// on the taxable account's own gains aren't modelled.
+pub struct RmdPlan {
+    pub age: usize,       // Age at the start of retirement.
+    pub start_age: usize, // First year of RMDs: 73 under SECURE 2.0, 75 from 2033.
+    pub tax_deferred: f64,
+    pub tax_rate: f64, // e.g. 0.22 for the 22% bracket.
+}
+
+impl RmdPlan {
+    pub fn new(age: usize, start_age: usize, tax_deferred: f64, tax_rate: f64) -> Self {
+        assert!(start_age >= UNIFORM_LIFETIME_START);
+        assert!((0. ..1.).contains(&tax_rate));
+        RmdPlan {
+            age,
+            start_age,
+            tax_deferred,
+            tax_rate,
+        }
+    }
+}
+
+// One year of a run with RMDs.  Balances are at the end of the year, all in
+// real dollars.
+#[derive(Clone, Copy, Debug)]
+pub struct RmdYear {
+    pub year: usize,
+    pub age: usize,
+    pub rmd: f64,
+    pub withdrawn: f64, // From tax-deferred, before tax.  At least the RMD.
+    pub tax: f64,
+    pub reinvested: f64, // Forced out by the RMD and put back in taxable.
+    pub taxable: f64,
+    pub tax_deferred: f64,
+}
+
+impl Backtest {
+    pub fn rmd_run(
+        &self,
+        plan: &RmdPlan,
+        stock_fractions: &[f64],
+        year_offset: usize,
+        verbose: bool,
+    ) -> Vec<RmdYear> {
+        assert_eq!(self.periods_per_year, 1);
+        assert_eq!(stock_fractions.len(), self.real_expenses.len());
+        assert!(plan.tax_deferred <= self.start_portfolio);
+
+        let mut taxable = self.start_portfolio - plan.tax_deferred;
+        let mut tax_deferred = plan.tax_deferred;
+        let mut purchased: Vec<Option<Purchased>> = self.annuities.iter().map(|_| None).collect();
+
+        let mut years = Vec::new();
+        let mut inflation_factor = 1.0;
+        for (i, stock_fraction) in stock_fractions.iter().enumerate() {
+            self.check_fraction(*stock_fraction);
+            let index = self.index(year_offset, i);
+            let age = plan.age + i;
+
+            // Annuities are bought from taxable money.
+            let income = self.annuity_income(
+                &mut purchased,
+                i,
+                year_offset,
+                &mut taxable,
+                inflation_factor,
+            );
+            let expenses = self.nominal_expenses[i] / inflation_factor
+                + self.real_expenses[i]
+                + self.event_expenses(i, inflation_factor)
+                - income;
+
+            // Spend taxable first, then gross up what's still needed for tax.
+            let from_taxable = expenses.min(taxable.max(0.));
+            let needed = (expenses - from_taxable) / (1. - plan.tax_rate);
+            let rmd = match distribution_period(age) {
+                Some(period) if age >= plan.start_age => tax_deferred.max(0.) / period,
+                _ => 0.,
+            };
+            let withdrawn = needed.max(rmd);
+            let tax = withdrawn * plan.tax_rate;
+            let reinvested = withdrawn - tax - (expenses - from_taxable);
+            tax_deferred -= withdrawn;
+            taxable += reinvested - from_taxable;
+
+            // Rebalance both accounts, then a year passes.
+            (taxable, _) = self.grow(taxable, *stock_fraction, index);
+            (tax_deferred, _) = self.grow(tax_deferred, *stock_fraction, index);
+
+            inflation_factor *= self.inflation[index];
+            if verbose {
+                println!(
+                    "{} (age {}): expenses ${:.1}k, RMD ${:.1}k, withdrawn ${:.1}k, tax ${:.1}k, reinvested ${:.1}k, taxable ${:.3}k, tax-deferred ${:.3}k{}",
+                    self.label(index),
+                    age,
+                    expenses / 1e3,
+                    rmd / 1e3,
+                    withdrawn / 1e3,
+                    tax / 1e3,
+                    reinvested / 1e3,
+                    taxable / 1e3,
+                    tax_deferred / 1e3,
+                    self.event_labels(i)
+                );
+            }
+            years.push(RmdYear {
+                year: self.year_of(index),
+                age,
+                rmd,
+                withdrawn,
+                tax,
+                reinvested,
+                taxable,
+                tax_deferred,
+            });
+        }
+        years
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distribution_period, RmdPlan};
+    use crate::time_series;
+    use crate::Backtest;
+
+    #[test]
+    fn table() {
+        assert_eq!(distribution_period(71), None);
+        assert_eq!(distribution_period(72), Some(27.4));
+        assert_eq!(distribution_period(90), Some(12.2));
+        assert_eq!(distribution_period(120), Some(2.0));
+        assert_eq!(distribution_period(125), Some(2.0));
+    }
+
+    #[test]
+    fn rmds_force_taxable_income() {
+        // Plenty of taxable money, so nothing comes out of tax-deferred until
+        // the RMDs start at 73.
+        let backtest = Backtest::new(
+            1000.,
+            vec![20.; 5],
+            vec![0.; 5],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        );
+        let plan = RmdPlan::new(71, 73, 500., 0.2);
+        let years = backtest.rmd_run(&plan, &[0.; 5], 1990 - time_series::FIRST_YEAR, false);
+
+        assert_eq!(years[1].withdrawn, 0.);
+        let rmd = years[1].tax_deferred / 26.5;
+        assert!((years[2].rmd - rmd).abs() < 1e-9);
+        assert!((years[2].tax - rmd * 0.2).abs() < 1e-9);
+        assert!((years[2].reinvested - rmd * 0.8).abs() < 1e-9);
+
+        // Taxes are the only leak relative to a single account.
+        let total = years[4].taxable + years[4].tax_deferred;
+        let tax: f64 = years.iter().map(|y| y.tax).sum();
+        assert!(total < backtest.single_run(&[0.; 5], 1990 - time_series::FIRST_YEAR, false));
+        assert!(tax > 0.);
+    }
+}
@@ -0,0 +1,601 @@
+use crate::config::{self, Table};
+use crate::dataset::{Dataset, Datasets};
+use crate::glide;
+use crate::windows::{Sequence, StartYears};
+use crate::Backtest;
+use std::path::Path;
+
+// A plan described in a TOML file, so it can be kept, shared and rerun
+// without writing any Rust.  For example:
+//
+//     name = "4% rule, 60/40"
+//     start_portfolio = 1_000_000
+//     years = 30
+//
+//     [dataset]
+//     name = "us"             # Or file = "uk.csv" with format = "annual".
+//
+//     [[expenses]]
+//     amount = 40_000         # Per year.
+//     dollars = "real"        # Or "nominal", in start year dollars.
+//     from = 0                # First year of retirement it applies to.
+//     to = 30                 # Stops before this year.
+//
+//     [allocation]
+//     family = "constant"     # See glide::FAMILIES.  Or fractions = [...].
+//     params = [0.6]
+//
+//     [analysis]
+//     run = ["success_rate", "worst_year"]  # What `fire run` reports.
+//     horizons = [20, 30]         # Years, for swr_table; default `years`.
+//     report = "plan.html"        # Where `fire run` and `fire report` write
+//                                 # an HTML report.  Optional.
+//     start_years = [1926, 2000]  # Only windows starting in these years,
+//     exclude_years = [1929]      # less these.  Both optional.
+//     sequence = "circular"       # Or "historical", the default, or
+//                                 # "reversed".  See windows::Sequence.
+//
+// The dataset can also mix asset classes into its stocks and bonds, with
+// `stocks = { total_stock_market = 0.6, gold = 0.4 }` and likewise `bonds`,
+// after loading extra ones with `assets = { gold = "gold.csv" }`.  Extra
+// series such as CAPE load with `series = { cape = "cape.csv" }`.  Relative
+// file names are relative to the scenario file.
+//
+// Only annual history is bundled.  For monthly, export Shiller's ie_data
+// spreadsheet as CSV and give it with format = "shiller"; see
+// Dataset::from_shiller_csv.
+#[derive(Clone)]
+pub struct Scenario {
+    pub name: String,
+    pub start_portfolio: f64,
+    pub years: usize,
+    pub dataset: Dataset,
+    // Per year.  With monthly data they're spread evenly over the months.
+    pub real_expenses: Vec<f64>,
+    pub nominal_expenses: Vec<f64>,
+    pub allocation: Allocation,
+    pub analyses: Vec<Analysis>,
+    pub legacy: f64,            // Ending balance to aim for, for withdrawal rates.
+    pub horizons: Vec<usize>,   // Retirement lengths in years, for the SWR table.
+    pub report: Option<String>, // Where to write an HTML report, if anywhere.
+    // First and last start years of the windows analysed, and years to leave
+    // out.  Every window when None.
+    pub start_years: Option<(usize, usize)>,
+    pub exclude_years: Vec<usize>,
+    pub sequence: Sequence,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Allocation {
+    // A glide path family from glide::FAMILIES and its parameters.
+    Family { name: String, params: Vec<f64> },
+    // A stock fraction for each year.
+    Fractions(Vec<f64>),
+}
+
+impl Allocation {
+    // Stock fractions for each year of a retirement.
+    pub fn fractions(&self, years: usize) -> Vec<f64> {
+        match self {
+            Allocation::Family { name, params } => glide::family(name).unwrap().0(params, years),
+            Allocation::Fractions(fractions) => fractions.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Analysis {
+    SuccessRate,       // Windows, success rate and worst years.
+    WorstYear,         // The two worst start years and their ending values.
+    PerfectWithdrawal, // The perfect withdrawal rate of each start year.
+    Drawdown,          // Drawdowns and other path risk.
+    SwrTable,          // Safe withdrawal rates by start year and horizon.
+}
+
+const ANALYSES: [(&str, Analysis); 5] = [
+    ("success_rate", Analysis::SuccessRate),
+    ("worst_year", Analysis::WorstYear),
+    ("perfect_withdrawal", Analysis::PerfectWithdrawal),
+    ("drawdown", Analysis::Drawdown),
+    ("swr_table", Analysis::SwrTable),
+];
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        Self::from_toml(&text, dir).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // `dir` is where relative data file names are looked up.
+    pub fn from_toml(text: &str, dir: &Path) -> Result<Self, String> {
+        let table = config::parse(text)?;
+        config::check_keys(
+            &table,
+            "scenario",
+            &[
+                "name",
+                "start_portfolio",
+                "years",
+                "dataset",
+                "expenses",
+                "allocation",
+                "analysis",
+            ],
+        )?;
+
+        let start_portfolio = required(
+            config::get_f64(&table, "start_portfolio")?,
+            "start_portfolio",
+        )?;
+        if start_portfolio <= 0. {
+            return Err("start_portfolio must be positive".to_string());
+        }
+        let years = required(config::get_usize(&table, "years")?, "years")?;
+        if years == 0 {
+            return Err("years must be at least 1".to_string());
+        }
+
+        let dataset = match config::get_table(&table, "dataset")? {
+            Some(spec) => load_dataset(spec, dir)?,
+            None => Dataset::us_annual(),
+        };
+        if years * dataset.periods_per_year > dataset.len() {
+            return Err(format!(
+                "{} years is longer than dataset {}, which has {} years",
+                years,
+                dataset.name,
+                dataset.len() / dataset.periods_per_year
+            ));
+        }
+
+        let mut real_expenses = vec![0.; years];
+        let mut nominal_expenses = vec![0.; years];
+        for (i, expense) in config::get_tables(&table, "expenses")?
+            .into_iter()
+            .enumerate()
+        {
+            let context = format!("expenses {}", i + 1);
+            config::check_keys(expense, &context, &["amount", "dollars", "from", "to"])?;
+            let amount = required(config::get_f64(expense, "amount")?, "amount")
+                .map_err(|e| format!("{}: {}", context, e))?;
+            let expenses = match config::get_str(expense, "dollars")?.unwrap_or("real") {
+                "real" => &mut real_expenses,
+                "nominal" => &mut nominal_expenses,
+                other => {
+                    return Err(format!(
+                        "{}: dollars should be \"real\" or \"nominal\", not \"{}\"",
+                        context, other
+                    ))
+                }
+            };
+            let from = config::get_usize(expense, "from")?.unwrap_or(0);
+            let to = config::get_usize(expense, "to")?.unwrap_or(years);
+            if from >= to || to > years {
+                return Err(format!(
+                    "{}: years {} to {} aren't within the {} year retirement",
+                    context, from, to, years
+                ));
+            }
+            for e in &mut expenses[from..to] {
+                *e += amount;
+            }
+        }
+
+        let allocation = match config::get_table(&table, "allocation")? {
+            Some(spec) => allocation(spec, years)?,
+            None => return Err("missing [allocation]".to_string()),
+        };
+
+        let analysis = config::get_table(&table, "analysis")?
+            .cloned()
+            .unwrap_or_default();
+        config::check_keys(
+            &analysis,
+            "[analysis]",
+            &[
+                "run",
+                "legacy",
+                "horizons",
+                "report",
+                "start_years",
+                "exclude_years",
+                "sequence",
+            ],
+        )?;
+        let analyses = config::get_strings(&analysis, "run")?
+            .unwrap_or_else(|| vec!["success_rate"])
+            .into_iter()
+            .map(|name| {
+                ANALYSES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, a)| *a)
+                    .ok_or_else(|| format!("unknown analysis {}", name))
+            })
+            .collect::<Result<_, _>>()?;
+        let horizons = config::get_usizes(&analysis, "horizons")?.unwrap_or_else(|| vec![years]);
+        if horizons.iter().any(|&h| h == 0 || h > years) {
+            return Err(format!("horizons must be from 1 to {} years", years));
+        }
+
+        let start_years = match config::get_usizes(&analysis, "start_years")? {
+            Some(range) if range.len() == 2 && range[0] <= range[1] => Some((range[0], range[1])),
+            Some(_) => return Err("start_years must be [first, last]".to_string()),
+            None => None,
+        };
+        let exclude_years = config::get_usizes(&analysis, "exclude_years")?.unwrap_or_default();
+
+        let sequence = match config::get_str(&analysis, "sequence")?.unwrap_or("historical") {
+            "historical" => Sequence::Historical,
+            "circular" => Sequence::Circular,
+            "reversed" => Sequence::Reversed,
+            other => return Err(format!("unknown sequence {}", other)),
+        };
+
+        let scenario = Scenario {
+            name: config::get_str(&table, "name")?
+                .unwrap_or("scenario")
+                .to_string(),
+            start_portfolio,
+            years,
+            dataset,
+            real_expenses,
+            nominal_expenses,
+            allocation,
+            analyses,
+            legacy: config::get_f64(&analysis, "legacy")?.unwrap_or(0.),
+            horizons,
+            report: config::get_str(&analysis, "report")?
+                .map(|path| dir.join(path).to_string_lossy().into_owned()),
+            start_years,
+            exclude_years,
+            sequence,
+        };
+        let backtest = scenario.backtest();
+        if backtest
+            .ending_values(&scenario.stock_fractions())
+            .is_empty()
+        {
+            return Err(format!(
+                "no {} year window starts in the allowed start years",
+                years
+            ));
+        }
+        Ok(scenario)
+    }
+
+    // Stock fractions for every period.
+    pub fn stock_fractions(&self) -> Vec<f64> {
+        let per_year = self.allocation.fractions(self.years);
+        per_year
+            .iter()
+            .flat_map(|f| std::iter::repeat_n(*f, self.dataset.periods_per_year))
+            .collect()
+    }
+
+    pub fn backtest(&self) -> Backtest {
+        let periods = self.dataset.periods_per_year;
+        let per_period = |annual: &[f64]| -> Vec<f64> {
+            annual
+                .iter()
+                .flat_map(|e| std::iter::repeat_n(e / periods as f64, periods))
+                .collect()
+        };
+        let start_years = match self.start_years {
+            Some((first, last)) => StartYears::Range(first..=last),
+            None => StartYears::All,
+        };
+        Backtest::from_dataset(
+            self.start_portfolio,
+            per_period(&self.real_expenses),
+            per_period(&self.nominal_expenses),
+            &self.dataset,
+        )
+        .with_start_years(start_years.excluding(self.exclude_years.clone()))
+        .with_sequence(self.sequence)
+    }
+}
+
+fn required<T>(value: Option<T>, key: &str) -> Result<T, String> {
+    value.ok_or_else(|| format!("missing {}", key))
+}
+
+fn load_dataset(spec: &Table, dir: &Path) -> Result<Dataset, String> {
+    config::check_keys(
+        spec,
+        "[dataset]",
+        &[
+            "name", "file", "format", "assets", "series", "stocks", "bonds",
+        ],
+    )?;
+    let read = |file: &str| {
+        let path = dir.join(file);
+        std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+
+    let name = config::get_str(spec, "name")?;
+    let mut dataset = match config::get_str(spec, "file")? {
+        Some(file) => {
+            let name = name.unwrap_or(file);
+            let text = read(file)?;
+            match config::get_str(spec, "format")?.unwrap_or("annual") {
+                "annual" => Dataset::from_annual_csv(name, &text),
+                "shiller" => Dataset::from_shiller_csv(name, &text),
+                other => Err(format!(
+                    "format should be \"annual\" or \"shiller\", not \"{}\"",
+                    other
+                )),
+            }
+            .map_err(|e| format!("{}: {}", file, e))?
+        }
+        None => {
+            let name = name.unwrap_or("us");
+            Datasets::new()
+                .get(name)
+                .ok_or_else(|| format!("no built in dataset named {}", name))?
+                .clone()
+        }
+    };
+
+    for (key, load) in [
+        (
+            "assets",
+            Dataset::load_asset as fn(&mut Dataset, &str, &str) -> _,
+        ),
+        ("series", Dataset::load_series),
+    ] {
+        for (name, file) in config::get_table(spec, key)?.into_iter().flatten() {
+            let config::Value::String(file) = file else {
+                return Err(format!("{}.{} should be a file name", key, name));
+            };
+            load(&mut dataset, name, &read(file)?).map_err(|e| format!("{}: {}", file, e))?;
+        }
+    }
+
+    let weights = |key: &str| -> Result<Option<Vec<(String, f64)>>, String> {
+        let Some(table) = config::get_table(spec, key)? else {
+            return Ok(None);
+        };
+        table
+            .keys()
+            .map(|name| {
+                let weight = config::get_f64(table, name)?.unwrap();
+                Ok((name.clone(), weight))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    };
+    let stocks = weights("stocks")?;
+    let bonds = weights("bonds")?;
+    if stocks.is_some() || bonds.is_some() {
+        fn as_refs<'a>(
+            weights: &'a Option<Vec<(String, f64)>>,
+            default: &'a str,
+        ) -> Vec<(&'a str, f64)> {
+            match weights {
+                Some(w) => w.iter().map(|(n, x)| (n.as_str(), *x)).collect(),
+                None => vec![(default, 1.)],
+            }
+        }
+        dataset = dataset.mix(&as_refs(&stocks, "stocks"), &as_refs(&bonds, "bonds"))?;
+    }
+    Ok(dataset)
+}
+
+fn allocation(spec: &Table, years: usize) -> Result<Allocation, String> {
+    config::check_keys(spec, "[allocation]", &["family", "params", "fractions"])?;
+    let allocation = match (
+        config::get_str(spec, "family")?,
+        config::get_numbers(spec, "fractions")?,
+    ) {
+        (Some(name), None) => {
+            let (_, names) =
+                glide::family(name).ok_or_else(|| format!("unknown glide path family {}", name))?;
+            let params = config::get_numbers(spec, "params")?.unwrap_or_default();
+            if params.len() != names.len() {
+                return Err(format!(
+                    "family {} takes {} params: {}",
+                    name,
+                    names.len(),
+                    names.join(", ")
+                ));
+            }
+            Allocation::Family {
+                name: name.to_string(),
+                params,
+            }
+        }
+        (None, Some(fractions)) => {
+            if fractions.len() != years {
+                return Err(format!(
+                    "{} fractions given for a {} year retirement",
+                    fractions.len(),
+                    years
+                ));
+            }
+            Allocation::Fractions(fractions)
+        }
+        _ => return Err("[allocation] needs either family or fractions".to_string()),
+    };
+    if let Some(f) = allocation
+        .fractions(years)
+        .into_iter()
+        .find(|f| !(0. ..=1.).contains(f))
+    {
+        return Err(format!("stock fraction {} outside [0, 1]", f));
+    }
+    Ok(allocation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Allocation, Analysis, Scenario};
+    use crate::time_series;
+    use crate::Backtest;
+    use std::path::Path;
+
+    #[test]
+    fn load_scenario() {
+        let text = r#"
+            name = "Rising equity"
+            start_portfolio = 1_000_000
+            years = 30
+
+            [[expenses]]
+            amount = 40_000
+
+            [[expenses]]
+            amount = 12_000
+            dollars = "nominal"  # Mortgage.
+            to = 10
+
+            [allocation]
+            family = "linear"
+            params = [0.3, 0.7]
+
+            [analysis]
+            run = ["worst_year", "drawdown"]
+            horizons = [20, 30]
+            "#;
+        let scenario = Scenario::from_toml(text, Path::new("")).unwrap();
+        assert_eq!(scenario.name, "Rising equity");
+        assert_eq!(scenario.dataset.name, "us");
+        assert_eq!(scenario.nominal_expenses[9], 12_000.);
+        assert_eq!(scenario.nominal_expenses[10], 0.);
+        assert_eq!(
+            scenario.allocation,
+            Allocation::Family {
+                name: "linear".to_string(),
+                params: vec![0.3, 0.7]
+            }
+        );
+        assert_eq!(
+            scenario.analyses,
+            vec![Analysis::WorstYear, Analysis::Drawdown]
+        );
+        assert_eq!(scenario.horizons, vec![20, 30]);
+
+        let mut nominal = vec![0.; 30];
+        nominal[..10].fill(12_000.);
+        let by_hand = Backtest::new(
+            1_000_000.,
+            vec![40_000.; 30],
+            nominal,
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        );
+        let fractions = scenario.stock_fractions();
+        assert_eq!(fractions[29], 0.7);
+        assert_eq!(
+            scenario.backtest().worst_year(&fractions),
+            by_hand.worst_year(&fractions)
+        );
+    }
+
+    #[test]
+    fn example_file() {
+        let mut scenario = Scenario::load("scenarios/four_percent.toml").unwrap();
+        let (year, _, _, _) = scenario.backtest().worst_year(&scenario.stock_fractions());
+        assert_eq!(year, 1966);
+        scenario.start_years = Some((1946, 1990));
+        scenario.exclude_years = vec![1966];
+        let (year, _, _, _) = scenario.backtest().worst_year(&scenario.stock_fractions());
+        assert!((1946..=1990).contains(&year) && year != 1966);
+        assert!(Scenario::load("scenarios/missing.toml")
+            .err()
+            .unwrap()
+            .starts_with("scenarios/missing.toml: "));
+    }
+
+    #[test]
+    fn invalid_scenarios() {
+        let error = |text: &str| Scenario::from_toml(text, Path::new("")).err().unwrap();
+        let base = "start_portfolio = 1000\nyears = 30\n";
+        assert_eq!(error("years = 30\n"), "missing start_portfolio");
+        assert_eq!(error(base), "missing [allocation]");
+        assert_eq!(
+            error(&format!(
+                "{}[allocation]\nfamily = \"linear\"\nparams = [0.5]\n",
+                base
+            )),
+            "family linear takes 2 params: start, end"
+        );
+        assert_eq!(
+            error(&format!("{}[allocation]\nfractions = [0.5, 1.2]\n", base)),
+            "2 fractions given for a 30 year retirement"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}[allocation]\nfamily = \"constant\"\nparams = [1.5]\n",
+                base
+            )),
+            "stock fraction 1.5 outside [0, 1]"
+        );
+        let allocation = "[allocation]\nfamily = \"constant\"\nparams = [0.5]\n";
+        assert_eq!(
+            error(&format!(
+                "{}[[expenses]]\namount = 1\nto = 31\n{}",
+                base, allocation
+            )),
+            "expenses 1: years 0 to 31 aren't within the 30 year retirement"
+        );
+        assert_eq!(
+            error(&format!("{}stat_portfolio = 1\n", base)),
+            "unknown key stat_portfolio in scenario"
+        );
+        assert_eq!(
+            error("start_portfolio = 1000\nyears = 200\n"),
+            "200 years is longer than dataset us, which has 150 years"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}[dataset]\nstocks = {{ gold = 1 }}\n{}",
+                base, allocation
+            )),
+            "no asset class named gold"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}{}[analysis]\nstart_years = [2000, 1950]\n",
+                base, allocation
+            )),
+            "start_years must be [first, last]"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}{}[analysis]\nstart_years = [1995, 2010]\n",
+                base, allocation
+            )),
+            "no 30 year window starts in the allowed start years"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}{}[analysis]\nsequence = \"shuffled\"\n",
+                base, allocation
+            )),
+            "unknown sequence shuffled"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}{}[analysis]\nhorizons = [20, -30]\n",
+                base, allocation
+            )),
+            "horizons should hold whole numbers of at least 0, not -30"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}{}[analysis]\nstart_years = [1950.5, 1990]\n",
+                base, allocation
+            )),
+            "start_years should hold whole numbers of at least 0, not 1950.5"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}{}[analysis]\nexclude_years = [-1929]\n",
+                base, allocation
+            )),
+            "exclude_years should hold whole numbers of at least 0, not -1929"
+        );
+    }
+}
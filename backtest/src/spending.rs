@@ -0,0 +1,247 @@
+use crate::annuity::Purchased;
+use crate::{grid_search, Backtest, Range};
+
+// Valuation driven spending: each year withdraw a fraction of the portfolio
+// that depends on the market's CAPE at the time.  The withdrawal takes the
+// place of real_expenses; nominal expenses and events are still paid on top,
+// and annuity income still offsets them.
+#[derive(Clone, Copy, Debug)]
+pub enum Rule {
+    // Big ERN's CAPE rule: withdraw a + b / CAPE, e.g. 1.75% + 0.5 / CAPE.
+    Cape { a: f64, b: f64 },
+    // Withdraw the cyclically adjusted earnings yield, 1 / CAPE.
+    EarningsYield,
+}
+
+impl Rule {
+    // Withdrawal rate, as a fraction of the portfolio.
+    pub fn rate(&self, cape: f64) -> f64 {
+        match self {
+            Rule::Cape { a, b } => a + b / cape,
+            Rule::EarningsYield => 1. / cape,
+        }
+    }
+}
+
+// A single run under a spending rule, all in real dollars.
+#[derive(Clone, Debug)]
+pub struct SpendingRun {
+    pub withdrawals: Vec<f64>,
+    pub end_portfolio: f64,
+}
+
+impl SpendingRun {
+    pub fn min_withdrawal(&self) -> f64 {
+        self.withdrawals
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl Backtest {
+    // `cape` is the CAPE at the start of each period of the dataset, e.g.
+    // dataset.series(CAPE) once it's loaded, since none is bundled.  Returns
+    // None if the window has years without a CAPE.
+    pub fn spending_run(
+        &self,
+        rule: Rule,
+        cape: &[f64],
+        stock_fractions: &[f64],
+        year_offset: usize,
+        verbose: bool,
+    ) -> Option<SpendingRun> {
+        assert_eq!(cape.len(), self.bond_return.len());
+        assert_eq!(stock_fractions.len(), self.real_expenses.len());
+        let cape: Vec<f64> = (0..stock_fractions.len())
+            .map(|i| cape[self.index(year_offset, i)])
+            .collect();
+        if cape.iter().any(|c| c.is_nan()) {
+            return None;
+        }
+
+        let mut portfolio = self.start_portfolio;
+        let mut purchased: Vec<Option<Purchased>> = self.annuities.iter().map(|_| None).collect();
+        let mut withdrawals = Vec::new();
+        let mut inflation_factor = 1.0;
+        for (i, stock_fraction) in stock_fractions.iter().enumerate() {
+            self.check_fraction(*stock_fraction);
+            let index = self.index(year_offset, i);
+
+            // Remove the withdrawal and expenses at the start of the year.
+            let income = self.annuity_income(
+                &mut purchased,
+                i,
+                year_offset,
+                &mut portfolio,
+                inflation_factor,
+            );
+            let withdrawal = portfolio.max(0.) * rule.rate(cape[i]);
+            portfolio -= withdrawal
+                + self.nominal_expenses[i] / inflation_factor
+                + self.event_expenses(i, inflation_factor)
+                - income;
+            withdrawals.push(withdrawal);
+
+            // Rebalance, then a year passes.
+            (portfolio, _) = self.grow(portfolio, *stock_fraction, index);
+
+            inflation_factor *= self.inflation[index];
+            if verbose {
+                println!(
+                    "{}: CAPE {:.1}, withdrawal ${:.1}k ({:.2}%), portfolio value ${:.3}k{}",
+                    self.label(index),
+                    cape[i],
+                    withdrawal / 1e3,
+                    rule.rate(cape[i]) * 100.,
+                    portfolio / 1e3,
+                    self.event_labels(i)
+                );
+            }
+        }
+        Some(SpendingRun {
+            withdrawals,
+            end_portfolio: portfolio,
+        })
+    }
+
+    // Like worst_year, but measures each window by its smallest annual
+    // withdrawal, the leanest year of retirement.  Windows without a CAPE
+    // for every year are skipped.
+    pub fn worst_year_spending(
+        &self,
+        rule: Rule,
+        cape: &[f64],
+        stock_fractions: &[f64],
+    ) -> (usize, f64, usize, f64) {
+        let (worst_year, worst_value, second_worst_year, second_worst_value) =
+            self.worst_offsets(|year_offset| {
+                self.spending_run(rule, cape, stock_fractions, year_offset, false)
+                    .map(|run| run.min_withdrawal())
+            });
+        (
+            self.year_of(worst_year),
+            worst_value,
+            self.year_of(second_worst_year),
+            second_worst_value,
+        )
+    }
+
+    // Search the rule's parameters over `ranges` for the best worst-case
+    // minimum withdrawal, the way best_fractions searches glide paths.
+    // Returns the parameters, the withdrawal and the year it happened.
+    pub fn best_spending(
+        &self,
+        ranges: &[Range],
+        get_rule: fn(&[f64]) -> Rule,
+        cape: &[f64],
+        stock_fractions: &[f64],
+    ) -> (Vec<f64>, f64, usize) {
+        let (best_values, best_withdrawal, best_year) = grid_search(ranges, |values| {
+            let (year, withdrawal, _, _) =
+                self.worst_year_spending(get_rule(values), cape, stock_fractions);
+            (withdrawal, year)
+        });
+        (best_values, best_withdrawal, best_year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rule;
+    use crate::annuity::{Annuity, Mortality, Payout};
+    use crate::events::{Dollars, Event};
+    use crate::time_series;
+    use crate::{Backtest, Range};
+
+    fn backtest() -> Backtest {
+        Backtest::new(
+            1000.,
+            vec![0.; 30],
+            vec![0.; 30],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+    }
+
+    #[test]
+    fn constant_cape_is_constant_percentage() {
+        // With CAPE stuck at 25, the earnings yield rule is a 4% of portfolio
+        // rule.
+        let cape = vec![25.; time_series::YEARS];
+        let run = backtest()
+            .spending_run(Rule::EarningsYield, &cape, &[0.6; 30], 50, false)
+            .unwrap();
+        assert!((run.withdrawals[0] - 40.).abs() < 1e-9);
+        // Each year's withdrawal is 4% of what's left after last year's.
+        for i in 1..30 {
+            let stocks = time_series::TOTAL_STOCK_MARKET[50 + i - 1] / 100.;
+            let bonds = time_series::TOTAL_BOND_MARKET[50 + i - 1] / 100.;
+            let growth = 1. + 0.6 * stocks + 0.4 * bonds;
+            assert!((run.withdrawals[i] - run.withdrawals[i - 1] * 0.96 * growth).abs() < 1e-9);
+        }
+
+        let mut missing = cape.clone();
+        missing[55] = f64::NAN;
+        let backtest = backtest();
+        assert!(backtest
+            .spending_run(Rule::EarningsYield, &missing, &[0.6; 30], 50, false)
+            .is_none());
+        let (year, _, _, _) =
+            backtest.worst_year_spending(Rule::EarningsYield, &missing, &[0.6; 30]);
+        assert!(year > 1871 + 55 || year + 30 <= 1871 + 55);
+    }
+
+    #[test]
+    fn events_and_annuities_on_top() {
+        // Half the portfolio buys a nominal annuity paying a quarter of its
+        // premium a year, and a 30 roof is due in the first year.
+        let mortality = Mortality::from_qx(65, vec![0., 0., 0., 1.]);
+        let annuity = Annuity::new(
+            0.5,
+            0,
+            65,
+            Payout::Nominal,
+            mortality,
+            vec![0.; time_series::YEARS],
+        );
+        let backtest = Backtest::new(
+            1000.,
+            vec![0.; 4],
+            vec![0.; 4],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        )
+        .with_annuity(annuity)
+        .with_events(vec![Event::expense("roof", 0, 30., Dollars::Real)]);
+        let cape = vec![25.; time_series::YEARS];
+        let run = backtest
+            .spending_run(Rule::EarningsYield, &cape, &[0.6; 4], 50, false)
+            .unwrap();
+
+        assert!((run.withdrawals[0] - 20.).abs() < 1e-9);
+        let stocks = time_series::TOTAL_STOCK_MARKET[50] / 100.;
+        let bonds = time_series::TOTAL_BOND_MARKET[50] / 100.;
+        let portfolio = (500. - 20. - 30. + 125.) * (1. + 0.6 * stocks + 0.4 * bonds);
+        assert!((run.withdrawals[1] - portfolio * 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn search_cape_rule() {
+        let cape = vec![20.; time_series::YEARS];
+        let get_rule = |values: &[f64]| Rule::Cape {
+            a: values[0],
+            b: values[1],
+        };
+        let backtest = backtest();
+        let (values, withdrawal, _) = backtest.best_spending(
+            &[Range::new(0.01, 0.02, 0.01), Range::new(0., 0.5, 0.5)],
+            get_rule,
+            &cape,
+            &[0.7; 30],
+        );
+        let (_, worst, _, _) = backtest.worst_year_spending(get_rule(&values), &cape, &[0.7; 30]);
+        assert_eq!(withdrawal, worst);
+        assert_eq!(values.len(), 2);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::windows::{Sequence, StartYears};
+use crate::Backtest;
+use std::fmt;
+
+// Stress tests: named, reproducible bad histories to run a plan against.
+// Replays are the historical window starting in a famously bad year.  Shocks
+// are made up bad years spliced into history, replacing what happened, at a
+// chosen year of retirement.
+
+// One year of a shock: real stock and bond returns and inflation, in percent.
+// None keeps what history had.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShockYear {
+    pub stocks: Option<f64>,
+    pub bonds: Option<f64>,
+    pub inflation: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shock {
+    pub years: Vec<ShockYear>,
+}
+
+impl Shock {
+    // Stocks lose 40%, as in 1931 or 2008.
+    pub fn crash() -> Self {
+        Shock {
+            years: vec![ShockYear {
+                stocks: Some(-40.),
+                bonds: None,
+                inflation: None,
+            }],
+        }
+    }
+
+    // Stocks and bonds both lose 15% with 9% inflation, like 2022.
+    pub fn stagflation() -> Self {
+        Shock {
+            years: vec![ShockYear {
+                stocks: Some(-15.),
+                bonds: Some(-15.),
+                inflation: Some(9.),
+            }],
+        }
+    }
+
+    // Ten years of 0% real returns on everything.
+    pub fn lost_decade() -> Self {
+        Shock {
+            years: vec![
+                ShockYear {
+                    stocks: Some(0.),
+                    bonds: Some(0.),
+                    inflation: None,
+                };
+                10
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stress {
+    // The window starting in this year.
+    Replay(usize),
+    // The shock spliced into every window, starting this many years in.
+    Shock(Shock, usize),
+}
+
+// The stress tests everyone asks about.
+pub fn library() -> Vec<(&'static str, Stress)> {
+    vec![
+        ("retire in 1929", Stress::Replay(1929)),
+        ("retire in 1966", Stress::Replay(1966)),
+        ("retire in 2000", Stress::Replay(2000)),
+        ("-40% stocks in year 1", Stress::Shock(Shock::crash(), 0)),
+        (
+            "stocks and bonds -15%, 9% inflation",
+            Stress::Shock(Shock::stagflation(), 0),
+        ),
+        (
+            "lost decade of 0% real",
+            Stress::Shock(Shock::lost_decade(), 0),
+        ),
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StressResult {
+    pub name: String,
+    pub windows: usize,
+    // Years run.  A replay the data ends during is cut short, and its ending
+    // value is at the end of the data.
+    pub years: usize,
+    pub success_rate: f64,
+    pub worst_year: usize, // Start year of the worst window.
+    pub worst_value: f64,
+}
+
+impl fmt::Display for StressResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} windows of {} years, {:.1}% success, worst year starting {} (${:.3} M)",
+            self.name,
+            self.windows,
+            self.years,
+            self.success_rate * 100.,
+            self.worst_year,
+            self.worst_value / 1e6
+        )
+    }
+}
+
+impl Backtest {
+    // A copy of this backtest, for every window.
+    fn copy(&self) -> Backtest {
+        Backtest {
+            start_portfolio: self.start_portfolio,
+            real_expenses: self.real_expenses.clone(),
+            nominal_expenses: self.nominal_expenses.clone(),
+            bond_return: self.bond_return.clone(),
+            delta_return: self.delta_return.clone(),
+            inflation: self.inflation.clone(),
+            first_year: self.first_year,
+            first_month: self.first_month,
+            periods_per_year: self.periods_per_year,
+            leverage: self.leverage.clone(),
+            annuities: self.annuities.clone(),
+            events: self.events.clone(),
+            start_years: StartYears::All,
+            sequence: self.sequence,
+        }
+    }
+
+    // This backtest with the shock in place of history in the window at
+    // year_offset, starting `at` years in.  The rest of the data is as it was.
+    fn shocked(&self, shock: &Shock, year_offset: usize, at: usize) -> Backtest {
+        let mut shocked = self.copy();
+        let bond_return = &mut shocked.bond_return;
+        let delta_return = &mut shocked.delta_return;
+        let inflation = &mut shocked.inflation;
+        // Shocks are annual, so spread them evenly over the periods of a year.
+        let per_period =
+            |percent: f64| (1. + percent / 100.).powf(1. / self.periods_per_year as f64);
+        let periods = (0..shock.years.len() * self.periods_per_year)
+            .map(|p| {
+                (
+                    at * self.periods_per_year + p,
+                    &shock.years[p / self.periods_per_year],
+                )
+            })
+            .take_while(|(i, _)| *i < self.real_expenses.len());
+        for (i, year) in periods {
+            let index = self.index(year_offset, i);
+            let stocks = year
+                .stocks
+                .map_or(bond_return[index] + delta_return[index], per_period);
+            bond_return[index] = year.bonds.map_or(bond_return[index], per_period);
+            delta_return[index] = stocks - bond_return[index];
+            inflation[index] = year.inflation.map_or(inflation[index], per_period);
+        }
+        shocked
+    }
+
+    // The ending value of the window at year_offset with the shock spliced in
+    // `at` years into retirement.
+    pub fn shocked_run(
+        &self,
+        stock_fractions: &[f64],
+        shock: &Shock,
+        year_offset: usize,
+        at: usize,
+    ) -> f64 {
+        self.shocked(shock, year_offset, at)
+            .single_run(stock_fractions, year_offset, false)
+    }
+
+    pub fn stress_test(
+        &self,
+        name: &str,
+        stock_fractions: &[f64],
+        stress: &Stress,
+    ) -> StressResult {
+        let years = self.real_expenses.len() / self.periods_per_year;
+        match stress {
+            // A replay is history as it happened, whatever the sequence.
+            Stress::Replay(year) => {
+                assert!(*year >= self.first_year);
+                let offset = (year - self.first_year) * self.periods_per_year;
+                assert!(offset < self.bond_return.len(), "no data for {}", year);
+                let length = self
+                    .real_expenses
+                    .len()
+                    .min(self.bond_return.len() - offset);
+                let mut historical = self.copy();
+                historical.sequence = Sequence::Historical;
+                let value = historical.run(
+                    &self.real_expenses[..length],
+                    &stock_fractions[..length],
+                    offset,
+                    false,
+                    None,
+                );
+                StressResult {
+                    name: name.to_string(),
+                    windows: 1,
+                    years: length / self.periods_per_year,
+                    success_rate: (value >= 0.) as usize as f64,
+                    worst_year: *year,
+                    worst_value: value,
+                }
+            }
+            Stress::Shock(shock, at) => {
+                let values: Vec<(usize, f64)> = self
+                    .window_offsets(self.real_expenses.len())
+                    .into_iter()
+                    .map(|offset| {
+                        (
+                            offset,
+                            self.shocked_run(stock_fractions, shock, offset, *at),
+                        )
+                    })
+                    .collect();
+                let (worst_offset, worst_value) = values
+                    .iter()
+                    .copied()
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap();
+                let successes = values.iter().filter(|(_, v)| *v >= 0.).count();
+                StressResult {
+                    name: name.to_string(),
+                    windows: values.len(),
+                    years,
+                    success_rate: successes as f64 / values.len() as f64,
+                    worst_year: self.year_of(worst_offset),
+                    worst_value,
+                }
+            }
+        }
+    }
+
+    // Every stress test in the library, less replays of years the data
+    // doesn't cover.
+    pub fn stress_tests(&self, stock_fractions: &[f64]) -> Vec<StressResult> {
+        let covered = |year: usize| {
+            year >= self.first_year
+                && (year - self.first_year) * self.periods_per_year < self.bond_return.len()
+        };
+        library()
+            .iter()
+            .filter(|(_, stress)| match stress {
+                Stress::Replay(year) => covered(*year),
+                Stress::Shock(..) => true,
+            })
+            .map(|(name, stress)| self.stress_test(name, stock_fractions, stress))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Shock, ShockYear, Stress};
+    use crate::dataset::Dataset;
+    use crate::windows::Sequence;
+    use crate::Backtest;
+
+    #[test]
+    fn stress_tests() {
+        let mut dataset = Dataset::us_annual();
+        let backtest = |dataset: &Dataset| {
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], dataset)
+        };
+        let fractions = [0.6; 30];
+        let results = backtest(&dataset).stress_tests(&fractions);
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[1].name, "retire in 1966");
+        assert_eq!(
+            results[1].worst_value,
+            backtest(&dataset).single_run(&fractions, 1966 - 1871, false)
+        );
+        // The data ends 21 years into a 2000 retirement.
+        assert_eq!(results[2].years, 21);
+        // A crash at the start of every window is worse than history.
+        let history = backtest(&dataset).window_stats(&fractions);
+        assert_eq!(results[3].windows, history.windows);
+        assert!(results[3].worst_value < history.worst_value);
+        assert!(results[3].success_rate <= history.success_rate);
+
+        // Splicing a shock in is the same as editing the data by hand.
+        let shock = Shock {
+            years: vec![
+                ShockYear {
+                    stocks: Some(-30.),
+                    bonds: None,
+                    inflation: Some(12.),
+                },
+                ShockYear {
+                    stocks: None,
+                    bonds: Some(-5.),
+                    inflation: None,
+                },
+            ],
+        };
+        let shocked = backtest(&dataset).shocked_run(&fractions, &shock, 50, 3);
+        dataset.stocks[53] = -30.;
+        dataset.inflation[53] = 12.;
+        dataset.bonds[54] = -5.;
+        assert!((shocked - backtest(&dataset).single_run(&fractions, 50, false)).abs() < 1e-6);
+
+        // Shocks past the end of retirement do nothing.
+        let late = backtest(&Dataset::us_annual()).stress_test(
+            "late",
+            &fractions,
+            &Stress::Shock(Shock::lost_decade(), 30),
+        );
+        assert_eq!(late.worst_value, history.worst_value);
+
+        // Replays walk history forward whatever the sequence, and stop where
+        // the data does.
+        for sequence in [Sequence::Reversed, Sequence::Circular].iter().copied() {
+            let long = |sequence| {
+                Backtest::from_dataset(1_000_000., vec![40_000.; 60], vec![0.; 60], &dataset)
+                    .with_sequence(sequence)
+                    .stress_test("1966", &[0.6; 60], &Stress::Replay(1966))
+            };
+            assert_eq!(long(sequence), long(Sequence::Historical));
+            assert_eq!(long(sequence).years, 2021 - 1966);
+        }
+    }
+}
@@ -0,0 +1,587 @@
+// Charts as standalone SVG documents, written by hand so they need nothing
+// beyond the standard library.  Each function returns the whole document as a
+// string, ready to save to a .svg file or embed in HTML.
+
+use crate::Backtest;
+use std::fmt::Write;
+
+const FONT: &str = "font-family=\"sans-serif\" font-size=\"12\"";
+
+// Escape text for use in SVG content or attributes.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn start(svg: &mut String, width: usize, height: usize, title: &str) {
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+        width, height, width, height
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>",
+        width, height
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        "<text x=\"{}\" y=\"20\" text-anchor=\"middle\" {} font-size=\"16\">{}</text>",
+        width / 2,
+        FONT,
+        escape(title)
+    )
+    .unwrap();
+}
+
+// Red for the low end of the scale through yellow to green for the high end.
+fn colour(fraction: f64) -> String {
+    let fraction = fraction.clamp(0., 1.);
+    let (r, g) = if fraction < 0.5 {
+        (215., 48. + fraction * 2. * (200. - 48.))
+    } else {
+        (
+            215. - (fraction - 0.5) * 2. * (215. - 26.),
+            200. - (fraction - 0.5) * 2. * 50.,
+        )
+    };
+    format!("rgb({:.0},{:.0},{:.0})", r, g, 60.)
+}
+
+// A grid of cells, one column per year and one row per label, coloured by
+// value.  values[row][column] is None for cells without data, left blank.
+pub fn heat_map(
+    title: &str,
+    years: &[usize],
+    rows: &[String],
+    values: &[Vec<Option<f64>>],
+    unit: &str,
+) -> String {
+    assert_eq!(values.len(), rows.len());
+    let (left, top, cell_width, cell_height) = (80, 40, 6, 24);
+    let width = left + years.len() * cell_width + 100;
+    let height = top + rows.len() * cell_height + 40;
+
+    let known = values.iter().flatten().flatten();
+    let min = known.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = known.copied().fold(f64::NEG_INFINITY, f64::max);
+    let scale = |value: f64| {
+        if max > min {
+            (value - min) / (max - min)
+        } else {
+            0.5
+        }
+    };
+
+    let mut svg = String::new();
+    start(&mut svg, width, height, title);
+    for (r, row) in values.iter().enumerate() {
+        assert_eq!(row.len(), years.len());
+        let y = top + r * cell_height;
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" {}>{}</text>",
+            left - 6,
+            y + cell_height / 2 + 4,
+            FONT,
+            escape(&rows[r])
+        )
+        .unwrap();
+        for (c, value) in row.iter().enumerate() {
+            if let Some(value) = value {
+                writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{}: {:.2}{}</title></rect>",
+                    left + c * cell_width,
+                    y,
+                    cell_width,
+                    cell_height,
+                    colour(scale(*value)),
+                    years[c],
+                    value,
+                    escape(unit)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    // Label the start of every decade.
+    let bottom = top + rows.len() * cell_height;
+    for (c, year) in years.iter().enumerate() {
+        if year % 10 == 0 && (c == 0 || years[c - 1] != *year) {
+            writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" {}>{}</text>",
+                left + c * cell_width,
+                bottom + 16,
+                FONT,
+                year
+            )
+            .unwrap();
+        }
+    }
+
+    // A legend with the range of values.
+    let legend = left + years.len() * cell_width + 20;
+    for i in 0..10 {
+        writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"16\" height=\"{}\" fill=\"{}\"/>",
+            legend,
+            top + (9 - i) * 10,
+            10,
+            colour(i as f64 / 9.)
+        )
+        .unwrap();
+    }
+    for (y, value) in [(top + 8, max), (top + 100, min)] {
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" {}>{:.2}{}</text>",
+            legend + 20,
+            y,
+            FONT,
+            value,
+            escape(unit)
+        )
+        .unwrap();
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+// Colours for successive series, readable on white.
+const PALETTE: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+// One line of a line chart or one colour of bars in a bar chart.
+#[derive(Clone, Debug)]
+pub struct Series {
+    pub label: String,
+    pub values: Vec<f64>,
+}
+
+impl Series {
+    pub fn new(label: &str, values: Vec<f64>) -> Self {
+        Series {
+            label: label.to_string(),
+            values,
+        }
+    }
+}
+
+// Tick label formats, to the resolution given, e.g. the step between ticks.
+// Digits below that are float noise.
+
+pub fn dollars(value: f64, resolution: f64) -> String {
+    let sign = if value < 0. { "-" } else { "" };
+    let (unit, suffix) = if value.abs() >= 1e6 {
+        (1e6, "M")
+    } else if value.abs() >= 1e3 {
+        (1e3, "k")
+    } else {
+        (1., "")
+    };
+    format!(
+        "{}${}{}",
+        sign,
+        fixed(value.abs() / unit, resolution / unit),
+        suffix
+    )
+}
+
+pub fn percent(value: f64, resolution: f64) -> String {
+    format!("{}%", fixed(value * 100., resolution * 100.))
+}
+
+// `value` to as many decimals as `resolution` needs, less trailing zeros.
+fn fixed(value: f64, resolution: f64) -> String {
+    let decimals = if resolution >= 1. {
+        0
+    } else {
+        (-resolution.log10() - 1e-9).ceil() as usize
+    };
+    let text = format!("{:.*}", decimals, value);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+// Step between round numbers spanning [min, max], about five of them.
+fn tick_step(min: f64, max: f64) -> f64 {
+    let span = if max > min { max - min } else { 1. };
+    let magnitude = 10f64.powf((span / 5.).log10().floor());
+    [1., 2., 5., 10.]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|step| span / step <= 6.)
+        .unwrap()
+}
+
+// The round numbers themselves, from at or below min to at or above max.
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+    let step = tick_step(min, max);
+    let start = (min / step).floor() * step;
+    let mut ticks = Vec::new();
+    for i in 0.. {
+        let tick = start + i as f64 * step;
+        ticks.push(tick);
+        if tick >= max - step * 1e-9 {
+            break;
+        }
+    }
+    ticks
+}
+
+// The plot area of an x-y chart and its scales.
+struct Axes {
+    left: f64,
+    top: f64,
+    width: f64,
+    height: f64,
+    x: (f64, f64),
+    y: (f64, f64),
+}
+
+impl Axes {
+    const WIDTH: usize = 800;
+    const HEIGHT: usize = 450;
+
+    fn new(x: (f64, f64), y_min: f64, y_max: f64) -> Self {
+        // A flat series, e.g. an all bond glide path, would give a single
+        // tick and nothing to scale by.
+        let (y_min, y_max) = if y_max > y_min {
+            (y_min, y_max)
+        } else {
+            (y_min - 1., y_max + 1.)
+        };
+        let y_ticks = ticks(y_min, y_max);
+        Axes {
+            left: 80.,
+            top: 40.,
+            width: Self::WIDTH as f64 - 80. - 170.,
+            height: Self::HEIGHT as f64 - 40. - 50.,
+            x,
+            y: (y_ticks[0], *y_ticks.last().unwrap()),
+        }
+    }
+
+    fn x(&self, x: f64) -> f64 {
+        let span = if self.x.1 > self.x.0 {
+            self.x.1 - self.x.0
+        } else {
+            1.
+        };
+        self.left + (x - self.x.0) / span * self.width
+    }
+
+    fn y(&self, y: f64) -> f64 {
+        self.top + self.height - (y - self.y.0) / (self.y.1 - self.y.0) * self.height
+    }
+
+    // Grid lines and labels for both axes, and a line at zero.
+    fn draw(&self, svg: &mut String, x_label: &str, format: fn(f64, f64) -> String) {
+        let y_step = tick_step(self.y.0, self.y.1);
+        for tick in ticks(self.y.0, self.y.1) {
+            let y = self.y(tick);
+            writeln!(
+                svg,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\"/>",
+                self.left,
+                y,
+                self.left + self.width,
+                y,
+                if tick == 0. { "black" } else { "#ddd" }
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" {}>{}</text>",
+                self.left - 6.,
+                y + 4.,
+                FONT,
+                escape(&format(tick, y_step))
+            )
+            .unwrap();
+        }
+        let x_step = tick_step(self.x.0, self.x.1);
+        for tick in ticks(self.x.0, self.x.1) {
+            if tick < self.x.0 || tick > self.x.1 {
+                continue;
+            }
+            writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" {}>{}</text>",
+                self.x(tick),
+                self.top + self.height + 16.,
+                FONT,
+                fixed(tick, x_step)
+            )
+            .unwrap();
+        }
+        writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"black\"/>",
+            self.left, self.top, self.width, self.height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" {}>{}</text>",
+            self.left + self.width / 2.,
+            self.top + self.height + 38.,
+            FONT,
+            escape(x_label)
+        )
+        .unwrap();
+    }
+
+    fn legend(&self, svg: &mut String, series: &[Series]) {
+        for (i, s) in series.iter().enumerate() {
+            let y = self.top + 10. + i as f64 * 20.;
+            let x = self.left + self.width + 15.;
+            writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"12\" height=\"12\" fill=\"{}\"/>",
+                x,
+                y - 10.,
+                PALETTE[i % PALETTE.len()]
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" {}>{}</text>",
+                x + 18.,
+                y,
+                FONT,
+                escape(&s.label)
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn range(series: &[Series]) -> (f64, f64) {
+    let values = series.iter().flat_map(|s| s.values.iter().copied());
+    let min = values.clone().fold(0f64, f64::min);
+    let max = values.fold(0f64, f64::max);
+    (min, max)
+}
+
+// Lines through (x[i], series.values[i]).  Every series has a value for
+// every x.
+pub fn line_chart(
+    title: &str,
+    x_label: &str,
+    x: &[f64],
+    series: &[Series],
+    format: fn(f64, f64) -> String,
+) -> String {
+    let (min, max) = range(series);
+    let axes = Axes::new((x[0], *x.last().unwrap()), min, max);
+    let mut svg = String::new();
+    start(&mut svg, Axes::WIDTH, Axes::HEIGHT, title);
+    axes.draw(&mut svg, x_label, format);
+    for (i, s) in series.iter().enumerate() {
+        assert_eq!(s.values.len(), x.len());
+        let points: Vec<String> = x
+            .iter()
+            .zip(&s.values)
+            .map(|(x, y)| format!("{:.1},{:.1}", axes.x(*x), axes.y(*y)))
+            .collect();
+        writeln!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+            points.join(" "),
+            PALETTE[i % PALETTE.len()]
+        )
+        .unwrap();
+    }
+    axes.legend(&mut svg, series);
+    svg += "</svg>\n";
+    svg
+}
+
+// Bars side by side for each year, one colour per series.
+pub fn bar_chart(
+    title: &str,
+    years: &[usize],
+    series: &[Series],
+    format: fn(f64, f64) -> String,
+) -> String {
+    let (min, max) = range(series);
+    let first = years[0] as f64;
+    let axes = Axes::new((first, first + years.len() as f64), min, max);
+    let slot = axes.width / years.len() as f64;
+    let bar = slot / series.len() as f64;
+    let mut svg = String::new();
+    start(&mut svg, Axes::WIDTH, Axes::HEIGHT, title);
+    axes.draw(&mut svg, "Start year", format);
+    for (i, s) in series.iter().enumerate() {
+        assert_eq!(s.values.len(), years.len());
+        for (j, value) in s.values.iter().enumerate() {
+            let (y0, y1) = (axes.y(0.), axes.y(*value));
+            writeln!(
+                svg,
+                "<rect x=\"{:.2}\" y=\"{:.1}\" width=\"{:.2}\" height=\"{:.1}\" fill=\"{}\"><title>{} {}: {}</title></rect>",
+                axes.left + j as f64 * slot + i as f64 * bar,
+                y0.min(y1),
+                bar,
+                (y0 - y1).abs(),
+                PALETTE[i % PALETTE.len()],
+                escape(&s.label),
+                years[j],
+                // Two more digits than the axis.
+                escape(&format(*value, tick_step(axes.y.0, axes.y.1) / 100.))
+            )
+            .unwrap();
+        }
+    }
+    axes.legend(&mut svg, series);
+    svg += "</svg>\n";
+    svg
+}
+
+impl Backtest {
+    // The portfolio over each of the given windows, in real dollars, from the
+    // start of retirement.
+    pub fn path_chart(
+        &self,
+        title: &str,
+        stock_fractions: &[f64],
+        year_offsets: &[usize],
+    ) -> String {
+        let x = self.years_into(stock_fractions.len() + 1);
+        let series: Vec<Series> = year_offsets
+            .iter()
+            .map(|&year_offset| {
+                let mut path = vec![self.start_portfolio];
+                path.extend(self.portfolio_path(stock_fractions, year_offset));
+                Series::new(&self.label(year_offset), path)
+            })
+            .collect();
+        line_chart(title, "Years into retirement", &x, &series, dollars)
+    }
+
+    // The ending value of every window under each named glide path.
+    pub fn ending_values_chart(&self, title: &str, strategies: &[(&str, &[f64])]) -> String {
+        let mut years = Vec::new();
+        let series: Vec<Series> = strategies
+            .iter()
+            .map(|(label, stock_fractions)| {
+                let values = self.ending_values(stock_fractions);
+                years = values
+                    .iter()
+                    .map(|(offset, _)| self.year_of(*offset))
+                    .collect();
+                Series::new(label, values.into_iter().map(|(_, value)| value).collect())
+            })
+            .collect();
+        bar_chart(title, &years, &series, dollars)
+    }
+
+    // The stock fraction through retirement, as print_fractions prints it.
+    pub fn glide_path_chart(&self, title: &str, stock_fractions: &[f64]) -> String {
+        let x = self.years_into(stock_fractions.len());
+        line_chart(
+            title,
+            "Years into retirement",
+            &x,
+            &[Series::new("Stocks", stock_fractions.to_vec())],
+            percent,
+        )
+    }
+
+    // Time in years at the start of each of the first n periods.
+    fn years_into(&self, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| i as f64 / self.periods_per_year as f64)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dollars, escape, heat_map, percent, tick_step, ticks};
+    use crate::time_series;
+    use crate::Backtest;
+
+    #[test]
+    fn heat_map_cells() {
+        let svg = heat_map(
+            "Rates & <things>",
+            &[1929, 1930, 1931],
+            &["a".to_string(), "b".to_string()],
+            &[vec![Some(1.), Some(2.), None], vec![Some(3.), None, None]],
+            "%",
+        );
+        assert!(svg.contains("Rates &amp; &lt;things&gt;"));
+        // One cell per value, plus the background and the legend.
+        assert_eq!(svg.matches("<rect").count(), 3 + 1 + 10);
+        assert!(svg.contains(">1930</text>"));
+        assert!(svg.contains("rgb(215,48,60)"));
+        assert_eq!(escape("\"x\""), "&quot;x&quot;");
+    }
+
+    #[test]
+    fn tick_marks() {
+        assert_eq!(ticks(0., 1e6), vec![0., 2e5, 4e5, 6e5, 8e5, 1e6]);
+        assert_eq!(ticks(-0.3, 0.95), vec![-0.5, 0., 0.5, 1.]);
+        assert_eq!(dollars(2.5e6, 1e5), "$2.5M");
+        assert_eq!(dollars(-40e3, 1e4), "-$40k");
+        assert_eq!(dollars(95_819.2, 1.), "$95.819k");
+        assert_eq!(percent(0.6, 0.1), "60%");
+        // 0.1 steps aren't exact in binary, which doesn't show.
+        let labels: Vec<String> = ticks(0.25, 0.65)
+            .into_iter()
+            .map(|tick| percent(tick, tick_step(0.25, 0.65)))
+            .collect();
+        assert_eq!(labels, ["20%", "30%", "40%", "50%", "60%", "70%"]);
+        assert_eq!(percent(0.125, 0.005), "12.5%");
+    }
+
+    #[test]
+    fn charts() {
+        let backtest = Backtest::new(
+            1_000_000.,
+            vec![40_000.; 30],
+            vec![0.; 30],
+            time_series::TOTAL_STOCK_MARKET.to_vec(),
+            time_series::TOTAL_BOND_MARKET.to_vec(),
+        );
+        let fixed = [0.6; 30];
+        let rising: Vec<f64> = (0..30).map(|i| 0.3 + i as f64 / 50.).collect();
+
+        let svg = backtest.path_chart("Paths", &fixed, &[1929 - 1871, 1966 - 1871]);
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(">1966</text>"));
+
+        let svg = backtest.ending_values_chart(
+            "Ending values",
+            &[("60/40", &fixed[..]), ("rising", &rising)],
+        );
+        let windows = time_series::YEARS - 30 + 1;
+        assert_eq!(svg.matches("<title>").count(), 2 * windows);
+        assert!(svg.contains("<title>rising 1966: "));
+
+        let svg = backtest.glide_path_chart("Glide path", &rising);
+        assert!(svg.contains(">Stocks</text>"));
+        assert!(svg.contains(">80%</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+
+        let svg = backtest.glide_path_chart("All bonds", &[0.; 30]);
+        assert!(!svg.contains("NaN"));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::Backtest;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// The order in which a window goes through history.  Overlapping windows
+// count the first and last years of the data less than the middle ones: in
+// 30 year windows, 2008 is in only a few.  Circular windows wrap around from
+// the end of the data to the start, so every year starts a window and every
+// year is in as many windows as any other.  Reversed windows replay history
+// backwards from their start year, which is another plausible order for the
+// same returns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sequence {
+    Historical,
+    Circular,
+    Reversed,
+}
+
+// Which calendar years windows may start in, e.g. only post-war retirements.
+// With monthly data every month of an allowed year is a start.  See
+// Backtest::with_start_years.
+pub enum StartYears {
+    All,
+    Range(RangeInclusive<usize>),
+    List(Vec<usize>),
+    Where(Box<dyn Fn(usize) -> bool>),
+    Excluding(Box<StartYears>, Vec<usize>),
+}
+
+impl StartYears {
+    pub fn contains(&self, year: usize) -> bool {
+        match self {
+            StartYears::All => true,
+            StartYears::Range(range) => range.contains(&year),
+            StartYears::List(years) => years.contains(&year),
+            StartYears::Where(predicate) => predicate(year),
+            StartYears::Excluding(years, excluded) => {
+                years.contains(year) && !excluded.contains(&year)
+            }
+        }
+    }
+
+    // These years, less the excluded ones.
+    pub fn excluding(self, excluded: Vec<usize>) -> Self {
+        StartYears::Excluding(Box::new(self), excluded)
+    }
+}
+
+// Summary of a set of historical windows, like worst_year's output plus the
+// fraction of windows that didn't run out of money.
+#[derive(Clone, Debug)]
+pub struct WindowStats {
+    pub windows: usize,
+    pub worst_year: usize,
+    pub worst_value: f64,
+    pub second_worst_year: usize,
+    pub second_worst_value: f64,
+    pub success_rate: f64,
+}
+
+impl WindowStats {
+    // From (year offset, ending value) pairs.  None if there are no windows.
+    fn new(backtest: &Backtest, values: &[(usize, f64)]) -> Option<Self> {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (worst_offset, worst_value) = *sorted.first()?;
+        let (second_offset, second_worst_value) = sorted
+            .get(1)
+            .copied()
+            .unwrap_or((usize::MAX, f64::INFINITY));
+        let successes = values.iter().filter(|(_, v)| *v >= 0.).count();
+
+        Some(WindowStats {
+            windows: values.len(),
+            worst_year: backtest.year_of(worst_offset),
+            worst_value,
+            second_worst_year: if second_offset == usize::MAX {
+                usize::MAX
+            } else {
+                backtest.year_of(second_offset)
+            },
+            second_worst_value,
+            success_rate: successes as f64 / values.len() as f64,
+        })
+    }
+}
+
+impl fmt::Display for WindowStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} windows, {:.1}% success, worst year starting {} (${:.3} M",
+            self.windows,
+            self.success_rate * 100.,
+            self.worst_year,
+            self.worst_value / 1e6
+        )?;
+        if self.windows > 1 {
+            write!(
+                f,
+                ", second worst year {} ${:.3} M",
+                self.second_worst_year,
+                self.second_worst_value / 1e6
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Backtest {
+    // The ending value of every historical window, with its year offset.
+    pub fn ending_values(&self, stock_fractions: &[f64]) -> Vec<(usize, f64)> {
+        self.window_offsets(self.real_expenses.len())
+            .into_iter()
+            .map(|year_offset| {
+                (
+                    year_offset,
+                    self.single_run(stock_fractions, year_offset, false),
+                )
+            })
+            .collect()
+    }
+
+    // Fraction of windows that end with money left.
+    pub fn success_rate(&self, stock_fractions: &[f64]) -> f64 {
+        self.window_stats(stock_fractions).success_rate
+    }
+
+    pub fn window_stats(&self, stock_fractions: &[f64]) -> WindowStats {
+        WindowStats::new(self, &self.ending_values(stock_fractions)).unwrap()
+    }
+
+    // Stats for only the windows whose start offset satisfies `predicate`,
+    // e.g. "starting CAPE above 25".  None if no window does.
+    pub fn windows_where<P: Fn(usize) -> bool>(
+        &self,
+        stock_fractions: &[f64],
+        predicate: P,
+    ) -> Option<WindowStats> {
+        let values: Vec<(usize, f64)> = self
+            .ending_values(stock_fractions)
+            .into_iter()
+            .filter(|(offset, _)| predicate(*offset))
+            .collect();
+        WindowStats::new(self, &values)
+    }
+
+    // Stats for each group of windows, where `key` names the group a window
+    // belongs to from its start offset, or None to leave it out.  See
+    // Dataset::bucket_by for grouping by CAPE, inflation or bond yield.
+    pub fn group_windows<K: Fn(usize) -> Option<String>>(
+        &self,
+        stock_fractions: &[f64],
+        key: K,
+    ) -> BTreeMap<String, WindowStats> {
+        let mut groups: BTreeMap<String, Vec<(usize, f64)>> = BTreeMap::new();
+        for (offset, value) in self.ending_values(stock_fractions) {
+            if let Some(k) = key(offset) {
+                groups.entry(k).or_default().push((offset, value));
+            }
+        }
+        groups
+            .into_iter()
+            .map(|(k, values)| (k, WindowStats::new(self, &values).unwrap()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sequence, StartYears};
+    use crate::dataset::{Dataset, CAPE};
+    use crate::{Backtest, Range};
+
+    #[test]
+    fn group_by_cape() {
+        let mut dataset = Dataset::us_annual();
+        // Not Shiller's numbers, just something with a few buckets.
+        let cape: String = (1881..=2020)
+            .map(|year| format!("{},{}\n", year, 5 + (year % 30)))
+            .collect();
+        dataset.load_series(CAPE, &cape).unwrap();
+        assert!(dataset.series(CAPE).unwrap()[0].is_nan());
+        assert_eq!(dataset.series(CAPE).unwrap()[1890 - 1871], 5.);
+
+        let backtest =
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], &dataset);
+        let fractions = vec![0.7; 30];
+        let edges = [15., 25.];
+        let groups = backtest.group_windows(&fractions, dataset.bucket_by(CAPE, &edges).unwrap());
+        assert_eq!(groups.len(), 3);
+        let windows: usize = groups.values().map(|g| g.windows).sum();
+        assert_eq!(windows, 2020 - 1881 + 1 - 30 + 1);
+
+        let all = backtest.window_stats(&fractions);
+        assert_eq!(all.worst_year, backtest.worst_year(&fractions).0);
+        let high = backtest
+            .windows_where(&fractions, |offset| {
+                dataset.series(CAPE).unwrap()[offset] >= 25.
+            })
+            .unwrap();
+        assert_eq!(high.windows, groups[">= 25"].windows);
+        assert!(backtest.windows_where(&fractions, |_| false).is_none());
+    }
+
+    #[test]
+    fn start_years() {
+        let dataset = Dataset::us_annual();
+        let backtest = |years| {
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], &dataset)
+                .with_start_years(years)
+        };
+        let all = backtest(StartYears::All);
+        let post_war = backtest(StartYears::Range(1946..=2000));
+        let fractions = vec![0.6; 30];
+
+        let values = post_war.ending_values(&fractions);
+        assert_eq!(values.len(), 2020 - 1946 + 1 - 30 + 1);
+        assert_eq!(values[0], all.ending_values(&fractions)[1946 - 1871]);
+        // Years stay calendar years.
+        let (worst, value, second, _) = post_war.worst_year(&fractions);
+        assert_eq!(worst, 1966);
+        assert_eq!(value, all.single_run(&fractions, 1966 - 1871, false));
+        assert!(second >= 1946);
+        assert_eq!(
+            post_war.worst_year_general(|_, _| 0.6),
+            post_war.worst_year(&fractions)
+        );
+        assert_eq!(post_war.perfect_withdrawal_rates(&fractions, 0.)[0].0, 1946);
+
+        // One window has no second worst.
+        let one = backtest(StartYears::List(vec![1929]));
+        assert_eq!(one.worst_year(&fractions).2, usize::MAX);
+        let pre_war =
+            backtest(StartYears::Where(Box::new(|year| year < 1940)).excluding(vec![1929]));
+        let (_, _, year, _, _) =
+            pre_war.best_fractions(&[Range::new(0., 1., 0.5)], crate::glide::constant, 30);
+        assert!(year < 1940 && year != 1929);
+    }
+
+    #[test]
+    fn sequences() {
+        let dataset = Dataset::us_annual();
+        let backtest = |sequence| {
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], &dataset)
+                .with_sequence(sequence)
+        };
+        let historical = backtest(Sequence::Historical);
+        let circular = backtest(Sequence::Circular);
+        let reversed = backtest(Sequence::Reversed);
+        let fractions = vec![0.6; 30];
+
+        // Every year starts a circular window, and the ones that fit in the
+        // data are the historical windows.
+        let values = circular.ending_values(&fractions);
+        assert_eq!(values.len(), dataset.len());
+        let fits = historical.ending_values(&fractions);
+        assert_eq!(values[..fits.len()], fits[..]);
+        // 2008 wraps around to 1871.
+        let mut wrapped = dataset.clone();
+        for series in [
+            &mut wrapped.stocks,
+            &mut wrapped.bonds,
+            &mut wrapped.inflation,
+        ] {
+            series.rotate_left(2008 - 1871);
+        }
+        let by_hand = Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], &wrapped);
+        assert!(
+            (circular.single_run(&fractions, 2008 - 1871, false)
+                - by_hand.single_run(&fractions, 0, false))
+            .abs()
+                < 1e-6
+        );
+
+        // Reversed windows start at least 29 years in and run backwards.
+        let values = reversed.ending_values(&fractions);
+        assert_eq!(values.len(), fits.len());
+        assert_eq!(reversed.year_of(values[0].0), 1900);
+        let mut backwards = dataset.clone();
+        for series in [
+            &mut backwards.stocks,
+            &mut backwards.bonds,
+            &mut backwards.inflation,
+        ] {
+            series.reverse();
+        }
+        let by_hand =
+            Backtest::from_dataset(1_000_000., vec![40_000.; 30], vec![0.; 30], &backwards);
+        assert!(
+            (values[0].1 - by_hand.single_run(&fractions, dataset.len() - 30, false)).abs() < 1e-6
+        );
+        // Other analyses follow the sequence too.
+        assert!(reversed.worst_year(&fractions).0 >= 1900);
+        assert_eq!(reversed.drawdowns(&fractions).len(), fits.len());
+    }
+}
@@ -0,0 +1,124 @@
+// Exit codes of the fire binary: 0 if the plan succeeds, 1 if it fails, 2
+// for invalid input.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn fire(args: &[&str]) -> (i32, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_fire"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+// A scenario over the given annual CSV data, in a directory of its own.
+fn scenario(name: &str, csv: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("fire-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data.csv"), csv).unwrap();
+    let path = dir.join("scenario.toml");
+    std::fs::write(
+        &path,
+        "start_portfolio = 1000\n\
+         years = 2\n\
+         [dataset]\n\
+         file = \"data.csv\"\n\
+         [[expenses]]\n\
+         amount = 40\n\
+         [allocation]\n\
+         family = \"constant\"\n\
+         params = [0.6]\n",
+    )
+    .unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn plan_succeeds() {
+    let (code, stdout, _) = fire(&["run", "scenarios/four_percent.toml"]);
+    assert_eq!(code, 0);
+    assert!(stdout.contains("success rate: 100"));
+}
+
+#[test]
+fn plan_fails() {
+    // 94% of 100 bootstrapped histories succeed, short of the default 95%.
+    let (code, stdout, _) = fire(&[
+        "simulate",
+        "scenarios/four_percent.toml",
+        "--trials",
+        "100",
+        "--format",
+        "csv",
+    ]);
+    assert_eq!(code, 1);
+    assert!(stdout.starts_with("key,value\n"));
+    assert!(stdout.contains("\nsuccess_rate,94\n"));
+    let (code, _, _) = fire(&[
+        "simulate",
+        "scenarios/four_percent.toml",
+        "--trials",
+        "100",
+        "--min-success",
+        "0.9",
+    ]);
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn invalid_input() {
+    for args in [
+        &["run"][..],
+        &["bogus", "scenarios/four_percent.toml"],
+        &["run", "scenarios/missing.toml"],
+        &["run", "scenarios/four_percent.toml", "--bogus", "1"],
+        &["run", "scenarios/four_percent.toml", "--format", "xml"],
+        &["simulate", "scenarios/four_percent.toml", "--trials", "0"],
+        &[
+            "optimize",
+            "scenarios/four_percent.toml",
+            "--range",
+            "0:2:0.5",
+        ],
+    ]
+    .iter()
+    {
+        let (code, stdout, stderr) = fire(args);
+        assert_eq!(code, 2, "{:?}", args);
+        assert!(stdout.is_empty());
+        assert!(stderr.starts_with("fire: ") || stderr.starts_with("usage: "));
+    }
+}
+
+#[test]
+fn unfittable_data_is_invalid_input() {
+    // Too few years to fit a model to.
+    let short = scenario(
+        "short",
+        "year,stocks,bonds,inflation\n2000,5,2,3\n2001,-5,4,2\n",
+    );
+    let (code, _, stderr) = fire(&["simulate", &short, "--model", "lognormal"]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("too few"));
+    // Constant inflation has no variance.
+    let constant = scenario(
+        "constant",
+        "year,stocks,bonds,inflation\n\
+         2000,5,2,3\n2001,-5,4,3\n2002,12,-1,3\n2003,7,3,3\n2004,-2,1,3\n",
+    );
+    let (code, _, stderr) = fire(&["simulate", &constant, "--model", "student-t"]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("constant inflation"));
+    let (code, _, stderr) = fire(&["simulate", &short, "--model", "regimes"]);
+    assert_eq!(code, 2);
+    assert!(stderr.contains("--model regimes: too few"));
+    for path in [short, constant].iter() {
+        std::fs::remove_dir_all(PathBuf::from(path).parent().unwrap()).unwrap();
+    }
+}